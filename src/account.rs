@@ -0,0 +1,546 @@
+//! NetEase accounts behind `MUSIC_U`
+//!
+//! All configured and stored login cookies form an [`AccountPool`]. Every request
+//! goes out with the best account of the pool: logged in accounts before logged out
+//! ones, VIP before free, fewer recent rejections first, round robin between equals.
+//! Accounts NetEase rejects (`-460`, `405` rate limiting, `301` logged out) are
+//! skipped for a while and the request is retried with another account.
+//!
+//! The bot also checks every account in the background: it renews the login so the
+//! cookie does not expire, stores renewed cookies and reads the VIP membership.
+//! [`alert`] compares two checks and decides whether the admins should be told.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::database::Database;
+use crate::music_api::MusicApi;
+
+/// Days before the VIP membership expires at which the admins are warned
+const VIP_WARNING_DAYS: i64 = 3;
+/// How long a rate limited account is skipped
+const REJECTION_COOLDOWN: Duration = Duration::from_mins(10);
+/// Rejections older than this no longer count against an account
+const ERROR_WINDOW: Duration = Duration::from_hours(1);
+/// Rejection codes remembered per account
+const MAX_RECENT_ERRORS: usize = 16;
+
+/// Whether an API code means NetEase rejected the account rather than the request
+#[must_use]
+pub fn is_account_rejection(code: i32) -> bool {
+    matches!(code, -460 | 405 | 301)
+}
+
+/// Extract the `MUSIC_U` value from a submitted cookie, which may be the bare value
+/// or a whole `Cookie` header
+#[must_use]
+pub fn parse_music_u(text: &str) -> Option<String> {
+    let text = text.trim();
+    let value = if text.contains('=') {
+        text.split(';')
+            .filter_map(|part| part.trim().split_once('='))
+            .find(|(name, _)| *name == "MUSIC_U")?
+            .1
+    } else {
+        text
+    };
+    let value = value.trim();
+    (!value.is_empty() && !value.contains(char::is_whitespace)).then(|| value.to_string())
+}
+
+/// Result of the last account check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountHealth {
+    /// NetEase no longer accepts the cookie
+    LoggedOut,
+    Active {
+        user_id: u64,
+        nickname: String,
+        /// 黑胶 VIP level, 0 for plain VIP or no membership
+        vip_level: i64,
+        /// `None` without an active membership
+        vip_expires: Option<DateTime<Utc>>,
+    },
+    /// The check itself failed
+    Unknown(String),
+}
+
+impl AccountHealth {
+    /// Whether the account currently has an active VIP membership
+    #[must_use]
+    pub fn is_vip(&self) -> bool {
+        matches!(
+            self,
+            Self::Active {
+                vip_expires: Some(_),
+                ..
+            }
+        )
+    }
+
+    fn vip_expiring(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Active {
+                vip_expires: Some(expires),
+                ..
+            } if *expires - now <= TimeDelta::days(VIP_WARNING_DAYS) => Some(*expires),
+            _ => None,
+        }
+    }
+
+    /// One line summary for `/status`
+    #[must_use]
+    pub fn describe(&self) -> String {
+        match self {
+            Self::LoggedOut => "登录已失效".to_string(),
+            Self::Active {
+                nickname,
+                vip_level,
+                vip_expires,
+                ..
+            } => match vip_expires {
+                Some(expires) if *vip_level > 0 => format!(
+                    "{nickname} (黑胶VIP {vip_level} 级，{} 到期)",
+                    expires.format("%Y-%m-%d")
+                ),
+                Some(expires) => {
+                    format!("{nickname} (VIP，{} 到期)", expires.format("%Y-%m-%d"))
+                }
+                None => format!("{nickname} (非 VIP)"),
+            },
+            Self::Unknown(error) => format!("检查失败: {error}"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct AccountState {
+    health: Option<AccountHealth>,
+    /// Recent rejections, oldest first
+    errors: VecDeque<(Instant, i32)>,
+    cooldown_until: Option<Instant>,
+    /// When the cookie was last renewed
+    renewed_at: Option<Instant>,
+}
+
+impl AccountState {
+    fn recent_errors(&self) -> usize {
+        self.errors
+            .iter()
+            .filter(|(at, _)| at.elapsed() < ERROR_WINDOW)
+            .count()
+    }
+
+    fn cooling_down(&self) -> bool {
+        self.cooldown_until
+            .is_some_and(|cooldown_until| Instant::now() < cooldown_until)
+    }
+}
+
+/// A login cookie of the pool
+pub struct Account {
+    id: usize,
+    music_u: RwLock<String>,
+    state: Mutex<AccountState>,
+}
+
+// The cookie grants access to the account and must never end up in logs
+impl std::fmt::Debug for Account {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Account")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Account {
+    /// An account outside of any pool, such as one a user bound to themselves
+    #[must_use]
+    pub fn new(music_u: &str) -> Self {
+        Self {
+            id: 0,
+            music_u: RwLock::new(music_u.to_string()),
+            state: Mutex::new(AccountState::default()),
+        }
+    }
+
+    /// Stable number of the account, kept when its cookie is renewed. 0 outside of a pool.
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The current `MUSIC_U` cookie
+    #[must_use]
+    pub fn music_u(&self) -> String {
+        self.music_u.read().unwrap().clone()
+    }
+
+    /// Switch to a renewed cookie of the same account
+    pub fn set_music_u(&self, music_u: String) {
+        *self.music_u.write().unwrap() = music_u;
+        self.state.lock().unwrap().renewed_at = Some(Instant::now());
+    }
+
+    /// Result of the last check, `None` before the first one
+    #[must_use]
+    pub fn health(&self) -> Option<AccountHealth> {
+        self.state.lock().unwrap().health.clone()
+    }
+
+    /// Store the result of a check. A successful check lifts a cooldown.
+    pub fn set_health(&self, health: AccountHealth) {
+        let mut state = self.state.lock().unwrap();
+        if matches!(health, AccountHealth::Active { .. }) {
+            state.cooldown_until = None;
+        }
+        state.health = Some(health);
+    }
+
+    /// Nickname if known, otherwise the account number
+    #[must_use]
+    pub fn label(&self) -> String {
+        match self.health() {
+            Some(AccountHealth::Active { nickname, .. }) => nickname,
+            _ if self.id == 0 => "bound user account".to_string(),
+            _ => format!("#{}", self.id),
+        }
+    }
+
+    /// Record that NetEase rejected the account with `code`
+    pub fn record_rejection(&self, code: i32) {
+        let mut state = self.state.lock().unwrap();
+        state.errors.push_back((Instant::now(), code));
+        while state.errors.len() > MAX_RECENT_ERRORS {
+            state.errors.pop_front();
+        }
+        if code == 301 {
+            state.health = Some(AccountHealth::LoggedOut);
+        } else {
+            state.cooldown_until = Some(Instant::now() + REJECTION_COOLDOWN);
+        }
+    }
+
+    /// Short summary for `/status`
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut summary = format!(
+            "#{} {}",
+            self.id,
+            state
+                .health
+                .as_ref()
+                .map_or_else(|| "检查中".to_string(), AccountHealth::describe)
+        );
+        if state.cooling_down() {
+            summary.push_str("，冷却中");
+        }
+        let codes: Vec<String> = state
+            .errors
+            .iter()
+            .filter(|(at, _)| at.elapsed() < ERROR_WINDOW)
+            .map(|(_, code)| code.to_string())
+            .collect();
+        if !codes.is_empty() {
+            summary.push_str(&format!("，最近错误: {}", codes.join(" ")));
+        }
+        summary
+    }
+
+    /// Whether NetEase said the cookie is no longer logged in
+    #[must_use]
+    pub fn logged_out(&self) -> bool {
+        matches!(
+            self.state.lock().unwrap().health,
+            Some(AccountHealth::LoggedOut)
+        )
+    }
+
+    /// Ranking of the account, higher is better
+    fn score(&self) -> (bool, bool, i64, std::cmp::Reverse<usize>) {
+        let state = self.state.lock().unwrap();
+        let (vip, vip_level) = match &state.health {
+            Some(health @ AccountHealth::Active { vip_level, .. }) => (health.is_vip(), *vip_level),
+            _ => (false, 0),
+        };
+        (
+            !state.cooling_down(),
+            vip,
+            vip_level,
+            std::cmp::Reverse(state.recent_errors()),
+        )
+    }
+}
+
+/// Login cookies shared by all requests
+#[derive(Debug, Default)]
+pub struct AccountPool {
+    accounts: RwLock<Vec<Arc<Account>>>,
+    next_id: AtomicUsize,
+    /// Round robin position between equally good accounts
+    cursor: AtomicUsize,
+}
+
+impl AccountPool {
+    #[must_use]
+    pub fn new(cookies: &[String]) -> Self {
+        let pool = Self::default();
+        for music_u in cookies {
+            pool.add(music_u);
+        }
+        pool
+    }
+
+    /// Add a cookie unless it is already in the pool. Returns the account.
+    pub fn add(&self, music_u: &str) -> Arc<Account> {
+        let mut accounts = self.accounts.write().unwrap();
+        if let Some(account) = accounts.iter().find(|a| a.music_u() == music_u) {
+            return Arc::clone(account);
+        }
+        let account = Arc::new(Account {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            ..Account::new(music_u)
+        });
+        accounts.push(Arc::clone(&account));
+        account
+    }
+
+    /// Drop an account from the pool
+    pub fn remove(&self, id: usize) {
+        self.accounts.write().unwrap().retain(|a| a.id != id);
+    }
+
+    /// Drop one of two logins of the same user, keeping the most recently renewed one.
+    /// Without renewals the one added last, which came from the database, is kept.
+    /// Returns the dropped account.
+    pub fn remove_duplicate(&self, a: &Arc<Account>, b: &Arc<Account>) -> Arc<Account> {
+        let freshness = |account: &Account| (account.state.lock().unwrap().renewed_at, account.id);
+        let dropped = if freshness(a) < freshness(b) { a } else { b };
+        self.remove(dropped.id);
+        Arc::clone(dropped)
+    }
+
+    /// All accounts in the order they were added
+    #[must_use]
+    pub fn accounts(&self) -> Vec<Arc<Account>> {
+        self.accounts.read().unwrap().clone()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.accounts.read().unwrap().is_empty()
+    }
+
+    /// Whether any account may still be logged in
+    #[must_use]
+    pub fn has_login(&self) -> bool {
+        self.accounts
+            .read()
+            .unwrap()
+            .iter()
+            .any(|a| !a.logged_out())
+    }
+
+    /// The best account for the next request, skipping the accounts in `tried`.
+    /// `None` when no logged in account is left.
+    #[must_use]
+    pub fn pick(&self, tried: &[usize]) -> Option<Arc<Account>> {
+        let candidates: Vec<(Arc<Account>, _)> = self
+            .accounts
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|a| !tried.contains(&a.id) && !a.logged_out())
+            .map(|a| (Arc::clone(a), a.score()))
+            .collect();
+        let best = candidates.iter().map(|(_, score)| *score).max()?;
+        let best: Vec<&Arc<Account>> = candidates
+            .iter()
+            .filter(|(_, score)| *score == best)
+            .map(|(account, _)| account)
+            .collect();
+        let index = self.cursor.fetch_add(1, Ordering::Relaxed) % best.len();
+        Some(Arc::clone(best[index]))
+    }
+}
+
+/// Check an account, renewing its login and storing the renewed cookie
+pub async fn check_account(
+    api: &MusicApi,
+    database: &Database,
+    account: &Account,
+) -> AccountHealth {
+    let music_u = account.music_u();
+
+    let profile = match api.get_account_profile(&music_u).await {
+        Ok(Some(profile)) => profile,
+        Ok(None) => return AccountHealth::LoggedOut,
+        Err(e) => return AccountHealth::Unknown(e.to_string()),
+    };
+
+    match api.refresh_login(&music_u).await {
+        Ok(Some(renewed)) if renewed != music_u => {
+            match database
+                .replace_account(
+                    &music_u,
+                    &renewed,
+                    profile.user_id as i64,
+                    Some(&profile.nickname),
+                )
+                .await
+            {
+                Ok(()) => tracing::info!("NetEase login of {} renewed", profile.nickname),
+                Err(e) => tracing::warn!("Failed to store renewed NetEase login: {}", e),
+            }
+            account.set_music_u(renewed);
+        }
+        Ok(_) => tracing::debug!("NetEase login of {} refreshed", profile.nickname),
+        Err(e) => tracing::warn!("Failed to refresh NetEase login: {}", e),
+    }
+
+    match api.get_vip_info(&account.music_u()).await {
+        Ok(vip) => AccountHealth::Active {
+            user_id: profile.user_id,
+            nickname: profile.nickname,
+            vip_level: vip.level,
+            vip_expires: vip.expire_time.and_then(DateTime::from_timestamp_millis),
+        },
+        Err(e) => AccountHealth::Unknown(e.to_string()),
+    }
+}
+
+/// Message for the admins when an account got worse between two checks.
+/// `previous` is the last successful check, `None` right after startup.
+#[must_use]
+pub fn alert(
+    label: &str,
+    previous: Option<&AccountHealth>,
+    current: &AccountHealth,
+    now: DateTime<Utc>,
+) -> Option<String> {
+    match current {
+        AccountHealth::LoggedOut if previous != Some(&AccountHealth::LoggedOut) => Some(format!(
+            "⚠️ 网易云账号 {label} 登录已失效，请使用 /login 重新登录"
+        )),
+        AccountHealth::Active { .. } => {
+            if let Some(expires) = current.vip_expiring(now) {
+                if previous.is_some_and(|previous| previous.vip_expiring(now).is_some()) {
+                    return None;
+                }
+                return Some(format!(
+                    "⚠️ 网易云账号 {label} 的 VIP 将于 {} 到期，到期后将无法下载 VIP 歌曲和无损音质",
+                    expires.format("%Y-%m-%d %H:%M")
+                ));
+            }
+            if !current.is_vip() && previous.is_some_and(AccountHealth::is_vip) {
+                return Some(format!(
+                    "⚠️ 网易云账号 {label} 的 VIP 已过期，VIP 歌曲和无损音质将不可用"
+                ));
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn active(vip_level: i64, vip_expires: Option<DateTime<Utc>>) -> AccountHealth {
+        AccountHealth::Active {
+            user_id: 1,
+            nickname: "test".to_string(),
+            vip_level,
+            vip_expires,
+        }
+    }
+
+    #[test]
+    fn test_alert() {
+        let now = Utc::now();
+        let soon = Some(now + TimeDelta::days(1));
+        let later = Some(now + TimeDelta::days(30));
+        let logged_out = AccountHealth::LoggedOut;
+
+        // Logging out is reported once
+        assert!(alert("test", None, &logged_out, now).is_some());
+        assert!(alert("test", Some(&active(7, later)), &logged_out, now).is_some());
+        assert!(alert("test", Some(&logged_out), &logged_out, now).is_none());
+
+        // Expiring VIP is reported when it enters the warning window
+        assert!(alert("test", Some(&active(7, later)), &active(7, later), now).is_none());
+        assert!(alert("test", Some(&active(7, later)), &active(7, soon), now).is_some());
+        assert!(alert("test", None, &active(7, soon), now).is_some());
+        assert!(alert("test", Some(&active(7, soon)), &active(7, soon), now).is_none());
+
+        // Losing VIP is reported
+        assert!(alert("test", Some(&active(7, soon)), &active(0, None), now).is_some());
+        assert!(alert("test", Some(&active(0, None)), &active(0, None), now).is_none());
+
+        let unknown = AccountHealth::Unknown("timeout".to_string());
+        assert!(alert("test", None, &unknown, now).is_none());
+    }
+
+    #[test]
+    fn test_parse_music_u() {
+        assert_eq!(parse_music_u(" 00ABCDEF ").as_deref(), Some("00ABCDEF"));
+        assert_eq!(
+            parse_music_u("NMTID=abc; MUSIC_U=00ABCDEF; __csrf=123").as_deref(),
+            Some("00ABCDEF")
+        );
+        assert_eq!(parse_music_u("NMTID=abc; __csrf=123"), None);
+        assert_eq!(parse_music_u("not a cookie"), None);
+        assert_eq!(parse_music_u(""), None);
+    }
+
+    #[test]
+    fn test_pool_pick() {
+        let now = Utc::now();
+        let pool = AccountPool::new(&["a".to_string(), "b".to_string(), "c".to_string()]);
+        let [a, b, c] = [0, 1, 2].map(|i| Arc::clone(&pool.accounts()[i]));
+        a.set_health(active(0, None));
+        b.set_health(active(7, Some(now + TimeDelta::days(30))));
+        c.set_health(active(7, Some(now + TimeDelta::days(30))));
+
+        // VIP accounts take turns
+        let first = pool.pick(&[]).unwrap().id();
+        let second = pool.pick(&[]).unwrap().id();
+        assert_ne!(first, second);
+        assert!([b.id(), c.id()].contains(&first) && [b.id(), c.id()].contains(&second));
+
+        // Rate limited and logged out accounts are skipped
+        b.record_rejection(-460);
+        c.record_rejection(301);
+        assert_eq!(pool.pick(&[]).unwrap().id(), a.id());
+        assert_eq!(pool.pick(&[a.id()]).unwrap().id(), b.id());
+        assert!(pool.pick(&[a.id(), b.id()]).is_none());
+        assert!(pool.has_login());
+        assert!(b.summary().contains("-460"));
+    }
+
+    #[test]
+    fn test_remove_duplicate() {
+        let pool = AccountPool::new(&["config".to_string(), "stored".to_string()]);
+        let [config, stored] = [0, 1].map(|i| Arc::clone(&pool.accounts()[i]));
+
+        // The configured cookie was just renewed, the stored one is older
+        config.set_music_u("renewed".to_string());
+        let dropped = pool.remove_duplicate(&config, &stored);
+        assert_eq!(dropped.id(), stored.id());
+        assert_eq!(pool.accounts().len(), 1);
+        assert_eq!(pool.accounts()[0].music_u(), "renewed");
+
+        // Without renewals the cookie from the database wins
+        let pool = AccountPool::new(&["config".to_string(), "stored".to_string()]);
+        let [config, stored] = [0, 1].map(|i| Arc::clone(&pool.accounts()[i]));
+        let dropped = pool.remove_duplicate(&stored, &config);
+        assert_eq!(dropped.music_u(), "config");
+        assert_eq!(pool.accounts()[0].music_u(), "stored");
+    }
+}
@@ -480,6 +480,47 @@ impl AudioBuffer {
         }
     }
 
+    /// Move the buffer onto disk inside `dir` so a local Bot API server can read it.
+    /// Disk buffers are returned unchanged.
+    pub async fn persist_to_dir(self, dir: &str) -> Result<Self> {
+        match self {
+            Self::Disk { .. } => Ok(self),
+            Self::Memory { data, filename, .. } => {
+                let path = PathBuf::from(dir).join(&filename);
+                tokio::fs::write(&path, &data)
+                    .await
+                    .with_context(|| format!("Failed to write file: {}", path.display()))?;
+
+                tracing::debug!(
+                    "AudioBuffer: persisted memory buffer for local upload (path: {})",
+                    path.display()
+                );
+
+                Ok(Self::Disk {
+                    path,
+                    file: None,
+                    filename,
+                })
+            }
+        }
+    }
+
+    /// Convert to a `file://` InputFile for a local Bot API server (disk mode only)
+    pub fn to_local_input_file(&self) -> Result<InputFile> {
+        match self {
+            Self::Disk { path, .. } => {
+                let absolute = std::fs::canonicalize(path)
+                    .with_context(|| format!("Failed to resolve path: {}", path.display()))?;
+                let url = url::Url::from_file_path(&absolute)
+                    .map_err(|()| anyhow::anyhow!("Invalid file path: {}", absolute.display()))?;
+                Ok(InputFile::url(url))
+            }
+            Self::Memory { .. } => Err(anyhow::anyhow!(
+                "Memory buffers must be persisted before a local upload"
+            )),
+        }
+    }
+
     /// Get raw data (for memory mode) or read from disk
     pub async fn get_data(&self) -> Result<Vec<u8>> {
         match self {
@@ -1,19 +1,41 @@
 use anyhow;
 use futures_util::StreamExt;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use teloxide::dispatching::update_listeners::{webhooks, UpdateListener};
+use teloxide::dispatching::ShutdownToken;
 use teloxide::prelude::*;
 use teloxide::types::{
-    CallbackQuery, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery, InlineQueryResult,
-    InlineQueryResultArticle, InputFile, InputMessageContent, InputMessageContentText, Message,
-    MessageKind, ParseMode, ReplyMarkup,
+    CallbackQuery, ChosenInlineResult, InlineKeyboardButton, InlineKeyboardMarkup, InlineQuery,
+    InlineQueryResult, InlineQueryResultArticle, InlineQueryResultCachedAudio, InputFile,
+    InputMessageContent, InputMessageContentText, Message, MessageId, MessageKind, ParseMode,
+    ReplyMarkup, User,
 };
+use teloxide::utils::markdown;
 
+use crate::account::{self, parse_music_u, Account, AccountHealth};
 use crate::audio_buffer::{AudioBuffer, ThumbnailBuffer};
+use crate::cache::TtlCache;
 use crate::config::Config;
+use crate::credentials::CredentialCipher;
 use crate::database::{Database, SongInfo};
-use crate::error::Result;
-use crate::music_api::{format_artists, MusicApi};
-use crate::utils::{clean_filename, ensure_dir, parse_music_id};
+use crate::device::{self, ClientProfile};
+use crate::endpoints::{ApiEndpoint, EndpointPool};
+use crate::error::{BotError, Result};
+use crate::links::{find_message_links, find_short_links, MessageLink, NeteaseLink};
+use crate::music_api::{
+    format_artists, qr_code_png, qr_login_url, quality_label, Comment, CommentPage, CommentSort,
+    MusicApi, Playlist, Privilege, QrLoginStatus, SearchResult, SearchType, SongDetail, Toplist,
+    QUALITY_LEVELS,
+};
+use crate::ranking::{self, SongQuery};
+use crate::send_queue::SendQueue;
+use crate::utils::{
+    clean_filename, ensure_dir, format_duration, format_file_size, parse_music_id, truncate_chars,
+};
 
 pub struct BotState {
     pub config: Config,
@@ -21,8 +43,37 @@ pub struct BotState {
     pub music_api: MusicApi,
     pub download_semaphore: Arc<tokio::sync::Semaphore>,
     pub bot_username: String,
+    pub endpoints: Arc<EndpointPool>,
+    pub queue: SendQueue,
+    /// Search keywords by callback token
+    pub search_queries: TtlCache<String, (SearchType, String)>,
+    /// Search result pages by search type, keyword and page number
+    pub search_pages: TtlCache<(SearchType, String, u32), Arc<SearchResult>>,
+    /// Inline result pages by search type, keyword and offset
+    pub inline_pages: TtlCache<(SearchType, String, u32), Arc<SearchResult>>,
+    /// Search suggestions by partial keyword
+    pub suggestions: TtlCache<String, Arc<Vec<String>>>,
+    /// NetEase hot searches
+    pub hot_searches: TtlCache<(), Arc<Vec<String>>>,
+    /// NetEase charts
+    pub toplists: TtlCache<(), Arc<Vec<Toplist>>>,
+    /// Chart tracks by chart id
+    pub charts: TtlCache<u64, Arc<Playlist>>,
+    /// Songs currently being cached through the cache chat
+    pub warming: Mutex<HashSet<u64>>,
+    /// Radio sessions currently looking up their next track
+    pub radio_busy: Mutex<HashSet<i64>>,
+    /// Whether a QR code login is waiting to be scanned
+    pub login_pending: AtomicBool,
+    /// Users with a `/bind` QR code waiting to be scanned
+    pub bind_pending: Mutex<HashSet<i64>>,
+    /// Encrypts the NetEase accounts users bind
+    pub credentials: CredentialCipher,
 }
 
+/// Interval between NetEase account checks and login renewals
+const ACCOUNT_CHECK_INTERVAL: Duration = Duration::from_hours(6);
+
 pub async fn run(config: Config) -> Result<()> {
     tracing::info!("Starting Telegram bot...");
 
@@ -34,81 +85,42 @@ pub async fn run(config: Config) -> Result<()> {
     tracing::info!("Database initialized");
 
     // Initialize music API
-    let music_api = MusicApi::new(config.music_u.clone(), config.music_api.clone());
-    tracing::info!("Music API initialized");
-
-    // Initialize bot with custom API URL support
-    let bot = if !config.bot_api.is_empty() && config.bot_api != "https://api.telegram.org" {
-        // 使用自定义API URL
-        let api_url_str = if config.bot_api.ends_with("/bot") {
-            config.bot_api.clone()
-        } else {
-            format!("{}/bot", config.bot_api)
-        };
-
-        match reqwest::Url::parse(&api_url_str) {
-            Ok(api_url) => {
-                tracing::info!("Using custom Telegram API URL: {}", api_url);
-
-                // Create a custom HTTP client tuned for Cloudflare compatibility (mimic Go http client)
-                let client = reqwest::Client::builder()
-                    .use_rustls_tls()
-                    .user_agent("Go-http-client/2.0")
-                    .pool_max_idle_per_host(0)
-                    .danger_accept_invalid_certs(false)
-                    .timeout(std::time::Duration::from_secs(30))
-                    .no_gzip()
-                    .build()
-                    .unwrap();
-
-                // Create bot with custom client and API URL
-                let bot = Bot::with_client(&config.bot_token, client).set_api_url(api_url.clone());
-
-                // Test the connection with timeout and better error handling
-                tracing::info!("Testing custom API connection...");
-                match tokio::time::timeout(std::time::Duration::from_secs(15), bot.get_me()).await {
-                    Ok(Ok(_)) => {
-                        tracing::info!("✅ Custom API connection successful: {}", api_url);
-                        bot
-                    }
-                    Ok(Err(e)) => {
-                        let error_msg = format!("{e}");
-                        // Check if it's a CloudFlare challenge or other blocking issue
-                        if error_msg.contains("Just a moment")
-                            || error_msg.contains("cloudflare")
-                            || error_msg.contains("challenge")
-                        {
-                            tracing::warn!("❌ Custom API blocked by CloudFlare protection. Falling back to official API.");
-                        } else {
-                            tracing::warn!("❌ Custom API connection failed: {}. Falling back to official API.", e);
-                        }
-                        tracing::info!("Using fallback Telegram API URL: https://api.telegram.org");
-                        Bot::new(&config.bot_token)
-                    }
-                    Err(_) => {
-                        tracing::warn!(
-                            "❌ Custom API connection timeout (15s). Falling back to official API."
-                        );
-                        tracing::info!("Using fallback Telegram API URL: https://api.telegram.org");
-                        Bot::new(&config.bot_token)
-                    }
-                }
-            }
-            Err(e) => {
-                tracing::error!(
-                    "Invalid custom API URL '{}': {}. Using official API.",
-                    config.bot_api,
-                    e
-                );
-                tracing::info!("Using fallback Telegram API URL: https://api.telegram.org");
-                Bot::new(&config.bot_token)
+    let music_api = MusicApi::new(
+        &config.music_u,
+        config.music_api.clone(),
+        ClientProfile::from_config(&config),
+    );
+    device::init_device(&music_api, &database).await;
+    // Accounts logged in through /login join the configured ones
+    match database.accounts().await {
+        Ok(cookies) => {
+            for music_u in &cookies {
+                music_api.accounts().add(music_u);
             }
+            tracing::info!(
+                "Using {} NetEase accounts ({} logged in via /login)",
+                music_api.accounts().accounts().len(),
+                cookies.len()
+            );
         }
-    } else {
-        // 使用默认API URL
-        tracing::info!("Using default Telegram API URL: https://api.telegram.org");
-        Bot::new(&config.bot_token)
-    };
+        Err(e) => tracing::warn!("Failed to load stored NetEase accounts: {}", e),
+    }
+    tracing::info!("Music API initialized");
+
+    // Initialize Bot API endpoints and pick a healthy one
+    let endpoints = Arc::new(EndpointPool::new(&config));
+    tracing::info!("Testing Telegram API endpoints...");
+    endpoints.probe_all().await;
+    for endpoint in endpoints.endpoints() {
+        tracing::info!(
+            "Telegram API endpoint {} ({})",
+            endpoint.url,
+            endpoint.health_summary()
+        );
+    }
+    tracing::info!("Using Telegram API URL: {}", endpoints.current().url);
+    endpoints.spawn_health_checks();
+    let bot = endpoints.bot();
 
     // Log the API configuration
     tracing::info!("Music API configured: {}", &config.music_api);
@@ -127,49 +139,268 @@ pub async fn run(config: Config) -> Result<()> {
         music_api,
         download_semaphore: Arc::new(tokio::sync::Semaphore::new(10)), // 增加到 10 个并发下载
         bot_username,
+        endpoints,
+        queue: SendQueue::new(),
+        search_queries: TtlCache::new(Duration::from_hours(24), 4096),
+        search_pages: TtlCache::new(Duration::from_mins(5), 512),
+        inline_pages: TtlCache::new(Duration::from_mins(2), 1024),
+        suggestions: TtlCache::new(Duration::from_mins(2), 1024),
+        hot_searches: TtlCache::new(Duration::from_mins(10), 1),
+        toplists: TtlCache::new(Duration::from_hours(1), 1),
+        charts: TtlCache::new(Duration::from_mins(10), 64),
+        warming: Mutex::new(HashSet::new()),
+        radio_busy: Mutex::new(HashSet::new()),
+        login_pending: AtomicBool::new(false),
+        bind_pending: Mutex::new(HashSet::new()),
+        credentials: CredentialCipher::new(
+            config
+                .database_secret
+                .as_deref()
+                .unwrap_or(&config.bot_token),
+        ),
     });
+    spawn_account_checks(&bot_state);
 
     // Create dispatcher
     let handler = dptree::entry()
         .branch(Update::filter_message().endpoint(handle_message))
         .branch(Update::filter_callback_query().endpoint(handle_callback))
-        .branch(Update::filter_inline_query().endpoint(handle_inline_query));
+        .branch(Update::filter_inline_query().endpoint(handle_inline_query))
+        .branch(Update::filter_chosen_inline_result().endpoint(handle_chosen_inline_result));
+
+    // Updates come through the current endpoint, so the listener is restarted on the
+    // new one whenever the pool fails over
+    let stopping = Arc::new(AtomicBool::new(false));
+    let mut switches = bot_state.endpoints.subscribe();
+    loop {
+        // Failovers from here on restart the listener, even before its watch is running
+        switches.borrow_and_update();
+        let bot = bot_state.endpoints.bot();
+        let mut dispatcher = Dispatcher::builder(bot.clone(), handler.clone())
+            .dependencies(dptree::deps![Arc::clone(&bot_state)])
+            .default_handler(|upd| async move {
+                tracing::debug!("Unhandled update: {:?}", upd);
+            })
+            .build();
+        let watch = watch_listener(switches.clone(), dispatcher.shutdown_token(), &stopping);
+
+        if let Some(webhook_url) = &config.webhook_url {
+            let (listener, server) = webhook_listener(bot, &config, webhook_url).await?;
+            Box::pin(dispatcher.dispatch_with_listener(
+                listener,
+                LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+            ))
+            .await;
+            // Wait until the webhook is deleted and the port released, so the next
+            // listener can bind it and its webhook is not deleted by this one
+            match server.await {
+                Ok(Ok(())) => tracing::info!("Webhook removed"),
+                Ok(Err(e)) => {
+                    return Err(BotError::Config(format!("Webhook server failed: {e}")));
+                }
+                Err(e) => return Err(BotError::Other(e.into())),
+            }
+        } else {
+            tracing::info!("Receiving updates via long polling");
+            Box::pin(dispatcher.dispatch()).await;
+        }
+        watch.abort();
 
-    Dispatcher::builder(bot, handler)
-        .dependencies(dptree::deps![bot_state])
-        .default_handler(|upd| async move {
-            tracing::debug!("Unhandled update: {:?}", upd);
-        })
-        .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+        if stopping.load(Ordering::Relaxed) {
+            break;
+        }
+        tracing::warn!(
+            "Restarting update listener on {}",
+            bot_state.endpoints.current().url
+        );
+    }
+    tracing::info!("Bot stopped");
     Ok(())
 }
 
-async fn handle_message(bot: Bot, msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
-    if let MessageKind::Common(common) = &msg.kind {
-        if let teloxide::types::MediaKind::Text(text_content) = &common.media_kind {
-            let text = text_content.text.clone();
-            let bot = bot.clone();
-            let msg = msg.clone();
-            let state = state.clone();
+/// Shut the dispatcher behind `token` down on Ctrl-C, setting `stopping`, or when the
+/// endpoint pool switches to another endpoint
+fn watch_listener(
+    mut switches: tokio::sync::watch::Receiver<usize>,
+    token: ShutdownToken,
+    stopping: &Arc<AtomicBool>,
+) -> tokio::task::JoinHandle<()> {
+    let stopping = Arc::clone(stopping);
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => stopping.store(true, Ordering::Relaxed),
+            _ = switches.changed() => {}
+        }
+        // The dispatcher may still be starting up
+        loop {
+            match token.shutdown() {
+                Ok(shutdown) => {
+                    shutdown.await;
+                    break;
+                }
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    })
+}
 
-            tokio::spawn(async move {
-                // Handle commands
-                if text.starts_with('/') {
-                    if let Err(e) = handle_command(&bot, &msg, &state, &text).await {
-                        tracing::error!("Error handling command: {}", e);
+/// Check all NetEase accounts now and every `ACCOUNT_CHECK_INTERVAL`,
+/// messaging the admins when a login or VIP membership is about to stop working
+fn spawn_account_checks(state: &Arc<BotState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        let mut last_known: HashMap<usize, AccountHealth> = HashMap::new();
+        let mut interval = tokio::time::interval(ACCOUNT_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let mut users: HashMap<u64, Arc<Account>> = HashMap::new();
+            for account in state.music_api.accounts().accounts() {
+                let health =
+                    account::check_account(&state.music_api, &state.database, &account).await;
+                tracing::info!("NetEase account #{}: {}", account.id(), health.describe());
+                account.set_health(health.clone());
+                match &health {
+                    // A renewed cookie stored next to the configured one logs in the same user twice
+                    AccountHealth::Active { user_id, .. } => {
+                        if let Some(other) = users.get(user_id) {
+                            let dropped =
+                                state.music_api.accounts().remove_duplicate(other, &account);
+                            tracing::info!(
+                                "Dropping duplicate login #{} of {}",
+                                dropped.id(),
+                                account.label()
+                            );
+                            if let Err(e) = state.database.delete_account(&dropped.music_u()).await
+                            {
+                                tracing::warn!("Failed to delete duplicate NetEase login: {}", e);
+                            }
+                            last_known.remove(&dropped.id());
+                            if dropped.id() == account.id() {
+                                continue;
+                            }
+                        }
+                        users.insert(*user_id, Arc::clone(&account));
                     }
+                    AccountHealth::Unknown(_) => continue,
+                    AccountHealth::LoggedOut => {}
                 }
-                // Handle music URLs
-                else if text.contains("music.163.com")
-                    || text.contains("163cn.tv")
-                    || text.contains("163cn.link")
-                {
-                    if let Err(e) = handle_music_url(&bot, &msg, &state, &text).await {
-                        tracing::error!("Error handling music URL: {}", e);
+
+                if let Some(text) = account::alert(
+                    &account.label(),
+                    last_known.get(&account.id()),
+                    &health,
+                    chrono::Utc::now(),
+                ) {
+                    notify_admins(&state, &text).await;
+                }
+                last_known.insert(account.id(), health);
+            }
+        }
+    });
+}
+
+/// Send a private message to every bot admin
+async fn notify_admins(state: &BotState, text: &str) {
+    let bot = state.endpoints.bot();
+    for &admin in &state.config.bot_admin {
+        let chat = ChatId(admin);
+        if let Err(e) = state
+            .queue
+            .deliver(chat, bot.send_message(chat, text))
+            .await
+        {
+            tracing::warn!("Failed to notify admin {}: {}", admin, e);
+        }
+    }
+}
+
+/// Register the webhook with Telegram and serve updates on the embedded HTTP server.
+/// When the listener stops (e.g. on Ctrl-C) the webhook is deleted and the server shut
+/// down; the returned task finishes once both are done.
+async fn webhook_listener(
+    bot: Bot,
+    config: &Config,
+    webhook_url: &str,
+) -> Result<(
+    impl UpdateListener<Err = std::convert::Infallible>,
+    tokio::task::JoinHandle<std::result::Result<(), String>>,
+)> {
+    let url = reqwest::Url::parse(webhook_url)
+        .map_err(|e| BotError::Config(format!("Invalid webhook_url '{webhook_url}': {e}")))?;
+    let address: SocketAddr = config.webhook_listen.parse().map_err(|e| {
+        BotError::Config(format!(
+            "Invalid webhook_listen '{}': {e}",
+            config.webhook_listen
+        ))
+    })?;
+
+    let mut options = webhooks::Options::new(address, url);
+    if let Some(secret) = &config.webhook_secret {
+        options = options.secret_token(secret.clone());
+    }
+
+    tracing::info!(
+        "Receiving updates via webhook {} (listening on {})",
+        options.url,
+        address
+    );
+    let (mut listener, stopped, router) = webhooks::axum_to_router(bot, options).await?;
+    let stop_token = listener.stop_token();
+    let server = tokio::spawn(async move {
+        let result = match axum::Server::try_bind(&address) {
+            Ok(server) => server
+                .serve(router.into_make_service())
+                .with_graceful_shutdown(stopped)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        if result.is_err() {
+            stop_token.stop();
+        }
+        result
+    });
+    Ok((listener, server))
+}
+
+async fn handle_message(msg: Message, state: Arc<BotState>) -> ResponseResult<()> {
+    if let MessageKind::Common(_) = &msg.kind {
+        // Replies go through the currently healthy endpoint
+        let bot = state.endpoints.bot();
+
+        // Handle commands
+        if let Some(text) = msg.text().filter(|text| text.starts_with('/')) {
+            let text = text.to_string();
+            tokio::spawn(async move {
+                if let Err(e) = handle_command(&bot, &msg, &state, &text).await {
+                    tracing::error!("Error handling command: {}", e);
+                }
+            });
+            return Ok(());
+        }
+
+        // Handle NetEase links and share short links in text, captions and text links
+        let links = find_message_links(&msg);
+        if !links.is_empty() {
+            tokio::spawn(async move {
+                if let Err(e) = handle_message_links(&bot, &msg, &state, links).await {
+                    tracing::error!("Error handling music links: {}", e);
+                }
+            });
+        }
+        // Plain text in private chats, mentions and replies to the bot in groups
+        else if let Some(keyword) = search_keyword(&msg, &state.bot_username) {
+            tokio::spawn(async move {
+                match state.database.is_auto_search_enabled(msg.chat.id.0).await {
+                    Ok(true) => {
+                        if let Err(e) =
+                            handle_search_command(&bot, &msg, &state, Some(keyword)).await
+                        {
+                            tracing::error!("Error handling search: {}", e);
+                        }
                     }
+                    Ok(false) => {}
+                    Err(e) => tracing::error!("Failed to load chat settings: {}", e),
                 }
             });
         }
@@ -177,6 +408,112 @@ async fn handle_message(bot: Bot, msg: Message, state: Arc<BotState>) -> Respons
     Ok(())
 }
 
+/// Search keyword of a message that is not a command or link: the whole text in private
+/// chats; in groups the text after `@botname` or of a reply to one of the bot's messages
+fn search_keyword(msg: &Message, bot_username: &str) -> Option<String> {
+    // Messages sent through our own inline mode are results, not queries
+    if msg.via_bot.is_some() {
+        return None;
+    }
+    let text = msg.text()?.trim();
+
+    let keyword = if msg.chat.is_private() {
+        text.to_string()
+    } else {
+        let mention = format!("@{}", bot_username.to_ascii_lowercase());
+        // ASCII lowercasing keeps byte offsets intact
+        if let Some(pos) = text.to_ascii_lowercase().find(&mention) {
+            format!("{} {}", &text[..pos], &text[pos + mention.len()..])
+        } else if msg
+            .reply_to_message()
+            .and_then(Message::from)
+            .and_then(|user| user.username.as_deref())
+            .is_some_and(|username| username.eq_ignore_ascii_case(bot_username))
+        {
+            text.to_string()
+        } else {
+            return None;
+        }
+    };
+
+    let keyword = keyword.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!keyword.is_empty()).then_some(keyword)
+}
+
+/// Resolve the links of a message and handle them one after another, in order
+async fn handle_message_links(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    links: Vec<MessageLink>,
+) -> ResponseResult<()> {
+    let max_links = state.config.max_links_per_message;
+    let mut resolved: Vec<NeteaseLink> = Vec::new();
+    let mut failed_short_links = 0;
+
+    for link in links {
+        let link = match link {
+            MessageLink::Netease(link) => link,
+            MessageLink::Short(short_url) => {
+                match state.music_api.resolve_short_link(&short_url).await {
+                    Ok(link) => link,
+                    Err(e) => {
+                        tracing::warn!("Failed to resolve short link {}: {}", short_url, e);
+                        failed_short_links += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+        // Short links may point at a song that is also linked directly
+        if !resolved.contains(&link) {
+            resolved.push(link);
+        }
+        if resolved.len() > max_links {
+            break;
+        }
+    }
+
+    if resolved.is_empty() {
+        if failed_short_links > 0 {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(
+                        msg.chat.id,
+                        "无法解析分享短链接，请发送完整的网易云音乐链接",
+                    )
+                    .reply_to_message_id(msg.id),
+                )
+                .await?;
+        }
+        return Ok(());
+    }
+
+    if resolved.len() > max_links {
+        resolved.truncate(max_links);
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(
+                    msg.chat.id,
+                    format!("一条消息最多处理 {max_links} 个链接，其余链接已忽略"),
+                )
+                .reply_to_message_id(msg.id),
+            )
+            .await?;
+    }
+
+    for link in resolved {
+        if let Err(e) = handle_netease_link(bot, msg, state, link).await {
+            tracing::error!("Error handling {:?}: {}", link, e);
+        }
+    }
+    Ok(())
+}
+
 async fn handle_command(
     bot: &Bot,
     msg: &Message,
@@ -199,7 +536,7 @@ async fn handle_command(
 
     // Only log music/search commands and admin commands
     match command {
-        "music" | "netease" | "search" | "rmcache" => {
+        "music" | "netease" | "search" | "rmcache" | "login" | "bind" | "unbind" => {
             tracing::info!("Command: /{} from chat {}", command, msg.chat.id);
         }
         _ => {} // Don't log about/start/status commands
@@ -212,8 +549,17 @@ async fn handle_command(
         "search" => handle_search_command(bot, msg, state, args).await,
         "about" => handle_about_command(bot, msg, state).await,
         "lyric" => handle_lyric_command(bot, msg, state, args).await,
+        "info" => handle_info_command(bot, msg, state, args).await,
+        "comments" => handle_comments_command(bot, msg, state, args).await,
+        "similar" => handle_similar_command(bot, msg, state, args).await,
+        "radio" => handle_radio_command(bot, msg, state, args).await,
+        "top" => handle_top_command(bot, msg, state, args).await,
         "status" => handle_status_command(bot, msg, state).await,
         "rmcache" => handle_rmcache_command(bot, msg, state, args).await,
+        "login" => handle_login_command(bot, msg, state).await,
+        "bind" => handle_bind_command(bot, msg, state, args).await,
+        "unbind" => handle_unbind_command(bot, msg, state).await,
+        "autosearch" => handle_autosearch_command(bot, msg, state, args).await,
         _ => {
             // Unknown commands: don't respond (as requested)
             Ok(())
@@ -257,7 +603,7 @@ async fn handle_start_command(
                         send_audio.thumb = Some(InputFile::file_id(thumb_id));
                     }
 
-                    send_audio.await?;
+                    state.queue.deliver(msg.chat.id, send_audio).await?;
                     return Ok(());
                 }
             }
@@ -285,10 +631,15 @@ async fn handle_start_command(
         state.bot_username, state.bot_username
     );
 
-    bot.send_message(msg.chat.id, welcome_text)
-        .parse_mode(ParseMode::Html)
-        .disable_web_page_preview(true)
-        .reply_to_message_id(msg.id)
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, welcome_text)
+                .parse_mode(ParseMode::Html)
+                .disable_web_page_preview(true)
+                .reply_to_message_id(msg.id),
+        )
         .await?;
 
     Ok(())
@@ -305,27 +656,117 @@ async fn handle_help_command(
         发送网易云音乐链接给机器人，例如：\n\
         <code>https://music.163.com/song?id=12345</code>\n\n\
         2️⃣ <b>搜索音乐</b>\n\
-        使用 <code>/search &lt;关键词&gt;</code> 在私聊中搜索。\n\n\
+        私聊中直接发送关键词即可搜索，也可以使用 <code>/search &lt;关键词&gt;</code>。\n\
+        群组中可以发送 <code>@{0} &lt;关键词&gt;</code> 或回复机器人的消息进行搜索，\
+        使用 <code>/autosearch off</code> 关闭。\n\n\
         3️⃣ <b>Inline 搜索</b>\n\
-        在任何对话框输入 <code>@{} &lt;关键词&gt;</code> 即可快速搜索并分享音乐。\n\n\
+        在任何对话框输入 <code>@{0} &lt;关键词&gt;</code> 即可快速搜索并分享音乐。\n\n\
         4️⃣ <b>获取歌词</b>\n\
         使用 <code>/lyric &lt;关键词或ID&gt;</code> 获取歌词。\n\n\
-        5️⃣ <b>更多命令</b>\n\
+        5️⃣ <b>歌曲详情</b>\n\
+        使用 <code>/info &lt;关键词、ID或链接&gt;</code> 查看歌曲信息、可用音质和缓存状态，并选择音质下载。\n\
+        使用 <code>/comments &lt;关键词、ID或链接&gt;</code> 查看热门评论和最新评论。\n\
+        使用 <code>/similar &lt;关键词、ID或链接&gt;</code> 查看相似歌曲，\
+        <code>/radio &lt;关键词、ID或链接&gt;</code> 开启相似歌曲电台。\n\
+        使用 <code>/top</code> 查看排行榜和新碟上架。\n\n\
+        6️⃣ <b>绑定账号</b>\n\
+        使用 <code>/bind</code> 扫码绑定自己的网易云账号，也可以私聊发送 <code>/bind &lt;MUSIC_U&gt;</code>，\
+        之后你点播的歌曲将优先使用你的账号获取（VIP 歌曲和无损音质）。使用 <code>/unbind</code> 解除绑定。\n\n\
+        7️⃣ <b>更多命令</b>\n\
         • <code>/status</code> - 查看系统状态\n\
         • <code>/about</code> - 关于机器人\n\n\
         💬 <b>项目主页：</b> <a href=\"https://github.com/Lemonawa/music163bot-rust\">GitHub</a>",
         state.bot_username
     );
 
-    bot.send_message(msg.chat.id, help_text)
-        .parse_mode(ParseMode::Html)
-        .disable_web_page_preview(true)
-        .reply_to_message_id(msg.id)
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, help_text)
+                .parse_mode(ParseMode::Html)
+                .disable_web_page_preview(true)
+                .reply_to_message_id(msg.id),
+        )
         .await?;
 
     Ok(())
 }
 
+/// Find the song a keyword query means. Returns `None` after replying with an error or,
+/// when several results match about equally well, with a pick list whose buttons send
+/// `<action> <id>` callbacks.
+async fn resolve_song_query(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    query: &str,
+    action: &str,
+) -> ResponseResult<Option<u64>> {
+    let songs = match state
+        .music_api
+        .search_songs(query, ranking::CANDIDATES, 0)
+        .await
+    {
+        Ok(result) => result.songs,
+        Err(e) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("搜索失败: {e}"))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let ranked = ranking::rank(&SongQuery::parse(query), &songs);
+    let close = ranking::close_matches(&ranked);
+    match close {
+        [] => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, "未找到相关歌曲")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            Ok(None)
+        }
+        [(song, _)] => Ok(Some(song.id)),
+        candidates => {
+            let mut text = String::from("找到多首相近的歌曲，请选择:\n\n");
+            let mut buttons = Vec::new();
+            for (i, (song, _)) in (1..).zip(candidates.iter().take(5)) {
+                text.push_str(&format!(
+                    "{i}.「{}」 - {}\n    💿 {} · ⏱ {}\n",
+                    song.name,
+                    format_artists(&song.artists),
+                    song.album.name,
+                    format_duration(song.duration / 1000)
+                ));
+                buttons.push(InlineKeyboardButton::callback(
+                    i.to_string(),
+                    format!("{action} {}", song.id),
+                ));
+            }
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, text)
+                        .reply_markup(InlineKeyboardMarkup::new(vec![buttons]))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
 async fn handle_music_command(
     bot: &Bot,
     msg: &Message,
@@ -335,72 +776,64 @@ async fn handle_music_command(
     let args = args.unwrap_or_default();
 
     if args.is_empty() {
-        bot.send_message(msg.chat.id, "请输入歌曲ID或歌曲关键词")
-            .reply_to_message_id(msg.id)
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "请输入歌曲ID或歌曲关键词")
+                    .reply_to_message_id(msg.id),
+            )
             .await?;
         return Ok(());
     }
 
     // Try to parse as music ID first
     if let Some(music_id) = parse_music_id(&args) {
-        return process_music(bot, msg, state, music_id).await;
+        return process_music(bot, msg, state, msg.from(), music_id, None).await;
     }
 
-    // If not a number, search for the song
-    match state.music_api.search_songs(&args, 1).await {
-        Ok(songs) => {
-            if let Some(song) = songs.first() {
-                process_music(bot, msg, state, song.id).await
-            } else {
-                bot.send_message(msg.chat.id, "未找到相关歌曲")
-                    .reply_to_message_id(msg.id)
-                    .await?;
-                Ok(())
-            }
-        }
-        Err(e) => {
-            bot.send_message(msg.chat.id, format!("搜索失败: {e}"))
-                .reply_to_message_id(msg.id)
-                .await?;
-            Ok(())
-        }
+    // If not a number, search for the song that matches best
+    match resolve_song_query(bot, msg, state, &args, "music").await? {
+        Some(music_id) => process_music(bot, msg, state, msg.from(), music_id, None).await,
+        None => Ok(()),
     }
 }
 
+/// Send a song in reply to `msg`, from the cache when possible. `from` is the user who
+/// asked for it: the sender of a command, or whoever pressed a button on the bot's own
+/// message. `quality` asks for a specific level of `QUALITY_LEVELS` (or the next lower
+/// one that is available) instead of the best one.
 async fn process_music(
     bot: &Bot,
     msg: &Message,
     state: &Arc<BotState>,
+    from: Option<&User>,
     music_id: u64,
+    quality: Option<u64>,
 ) -> ResponseResult<()> {
+    deliver_music(bot, msg, state, from, music_id, quality).await?;
+    Ok(())
+}
+
+/// Like `process_music`, returning whether the song reached the chat
+async fn deliver_music(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    from: Option<&User>,
+    music_id: u64,
+    quality: Option<u64>,
+) -> ResponseResult<bool> {
     let music_id_i64 = music_id as i64;
 
     // Check if song is cached
     if let Ok(Some(cached_song)) = state.database.get_song_by_music_id(music_id_i64).await {
         // Validate cached file: must have file_id AND valid size (>1KB)
         if let Some(file_id) = &cached_song.file_id {
-            if cached_song.music_size > 1024 {
+            let wanted = quality.is_none_or(|br| cached_level(&cached_song) == br);
+            if cached_song.music_size > 1024 && wanted {
                 // Must be larger than 1KB
-                // bitrate fallback if missing
-                let bitrate = if cached_song.bit_rate > 0 {
-                    cached_song.bit_rate
-                } else {
-                    let dur = (if cached_song.duration > 0 {
-                        cached_song.duration
-                    } else {
-                        1
-                    }) as f64;
-                    (8.0 * cached_song.music_size as f64 / dur) as i64
-                };
-                let caption = build_caption(
-                    &cached_song.song_name,
-                    &cached_song.song_artists,
-                    &cached_song.song_album,
-                    &cached_song.file_ext,
-                    cached_song.music_size,
-                    bitrate,
-                    &state.bot_username,
-                );
+                let caption = cached_caption(&cached_song, &state.bot_username);
 
                 let keyboard = create_music_keyboard(
                     music_id,
@@ -408,136 +841,262 @@ async fn process_music(
                     &cached_song.song_artists,
                 );
 
-                bot.send_audio(msg.chat.id, InputFile::file_id(file_id))
-                    .caption(caption)
-                    .reply_markup(keyboard)
-                    .reply_to_message_id(msg.id)
+                state
+                    .queue
+                    .deliver(
+                        msg.chat.id,
+                        bot.send_audio(msg.chat.id, InputFile::file_id(file_id))
+                            .caption(caption)
+                            .reply_markup(keyboard)
+                            .reply_to_message_id(msg.id),
+                    )
                     .await?;
+                record_history(state, from, msg.chat.id, music_id).await;
 
-                return Ok(());
+                return Ok(true);
+            }
+            if wanted {
+                // Invalid cached file (too small), remove from database
+                tracing::warn!(
+                    "Removing invalid cached file for music_id {}: size {} bytes",
+                    music_id,
+                    cached_song.music_size
+                );
+                let _ = state.database.delete_song_by_music_id(music_id_i64).await;
             }
-            // Invalid cached file (too small), remove from database
-            tracing::warn!(
-                "Removing invalid cached file for music_id {}: size {} bytes",
-                music_id,
-                cached_song.music_size
-            );
-            let _ = state.database.delete_song_by_music_id(music_id_i64).await;
         }
     }
 
     // Send initial message
-    let status_msg = bot
-        .send_message(msg.chat.id, "🔄 正在获取歌曲信息...")
-        .reply_to_message_id(msg.id)
+    let status_msg = state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, "🔄 正在获取歌曲信息...")
+                .reply_to_message_id(msg.id),
+        )
         .await?;
 
+    let target = SendTarget::reply(msg, from, &status_msg);
+    let delivered = download_song(bot, state, music_id, quality, &target).await?;
+    if delivered {
+        // Delete status message
+        state
+            .queue
+            .progress(msg.chat.id, bot.delete_message(msg.chat.id, status_msg.id))
+            .await
+            .ok();
+        record_history(state, from, msg.chat.id, music_id).await;
+    }
+
+    Ok(delivered)
+}
+
+/// Remember the song for the requester's recent songs
+async fn record_history(state: &BotState, from: Option<&User>, chat_id: ChatId, music_id: u64) {
+    let Some(user) = from else {
+        return;
+    };
+    if let Err(e) = state
+        .database
+        .record_history(user.id.0 as i64, chat_id.0, music_id as i64)
+        .await
+    {
+        tracing::warn!("Failed to record song history: {}", e);
+    }
+}
+
+/// Where a downloaded song is uploaded to and who asked for it
+struct SendTarget {
+    chat_id: ChatId,
+    reply_to: Option<MessageId>,
+    /// Status message that shows progress and errors
+    status: Option<MessageId>,
+    from_user_id: i64,
+    from_user_name: String,
+    from_chat_name: String,
+}
+
+impl SendTarget {
+    /// Reply to `msg` on behalf of `from`, reporting progress in `status_msg`
+    fn reply(msg: &Message, from: Option<&User>, status_msg: &Message) -> Self {
+        Self {
+            chat_id: msg.chat.id,
+            reply_to: Some(msg.id),
+            status: Some(status_msg.id),
+            from_user_id: from.map_or(0, |u| u.id.0 as i64),
+            from_user_name: from.and_then(|u| u.username.clone()).unwrap_or_default(),
+            from_chat_name: msg.chat.username().unwrap_or("").to_string(),
+        }
+    }
+
+    /// Upload silently to the cache chat on behalf of `user`
+    fn cache_chat(chat_id: ChatId, user: &User) -> Self {
+        Self {
+            chat_id,
+            reply_to: None,
+            status: None,
+            from_user_id: user.id.0 as i64,
+            from_user_name: user.username.clone().unwrap_or_default(),
+            from_chat_name: String::new(),
+        }
+    }
+
+    /// Show `text` in the status message, if there is one
+    async fn set_status(
+        &self,
+        bot: &Bot,
+        state: &BotState,
+        text: impl Into<String>,
+    ) -> ResponseResult<()> {
+        if let Some(status) = self.status {
+            state
+                .queue
+                .progress(
+                    self.chat_id,
+                    bot.edit_message_text(self.chat_id, status, text),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Fetch, download and upload a song to `target`, saving its `file_id`.
+/// With `quality` set, levels above it are skipped and the upload is not cached, so the
+/// cache keeps holding the best quality.
+/// Returns whether the song was delivered; failures are reported in the status message.
+async fn download_song(
+    bot: &Bot,
+    state: &Arc<BotState>,
+    music_id: u64,
+    quality: Option<u64>,
+    target: &SendTarget,
+) -> ResponseResult<bool> {
     // Get song details
     let song_detail = match state.music_api.get_song_detail(music_id).await {
         Ok(detail) => detail,
         Err(e) => {
-            bot.edit_message_text(
-                msg.chat.id,
-                status_msg.id,
-                format!("❌ 获取歌曲信息失败: {e}"),
-            )
-            .await?;
-            return Ok(());
+            target
+                .set_status(bot, state, format!("❌ 获取歌曲信息失败: {e}"))
+                .await?;
+            return Ok(false);
         }
     };
+    // Get download URL - walk down the quality ladder until a file fits the upload limit.
+    // FLAC is only worth asking for when MUSIC_U is available.
+    // The requester's own account is used first when they bound one.
+    let api = user_music_api(state, target.from_user_id).await;
+    let upload_limit = state.endpoints.upload_limit();
+    let levels = if api.has_login() {
+        QUALITY_LEVELS
+    } else {
+        &QUALITY_LEVELS[1..]
+    };
 
-    // Get download URL - try FLAC first if MUSIC_U is available, then fall back to MP3
-    let song_url = if state.music_api.music_u.is_some() {
-        // Try FLAC quality first for VIP users
-        match state.music_api.get_song_url(music_id, 999_000).await {
-            Ok(url) if !url.url.is_empty() => {
-                tracing::info!("Using FLAC quality for music_id {}", music_id);
-                url
+    let mut song_url = None;
+    let mut oversized: Option<u64> = None;
+    let mut last_error = None;
+    for &br in levels
+        .iter()
+        .filter(|&&br| quality.is_none_or(|max| br <= max))
+    {
+        match api.get_song_url(music_id, br).await {
+            Ok(url) if url.url.is_empty() => {}
+            Ok(url) if url.size > upload_limit => {
+                tracing::info!(
+                    "{} for music_id {} is {} bytes, over the upload limit of {} bytes",
+                    quality_label(url.level(br)),
+                    music_id,
+                    url.size,
+                    upload_limit
+                );
+                oversized.get_or_insert(url.size);
             }
-            _ => {
-                // Fallback to high quality MP3
+            Ok(url) => {
+                let level = url.level(br);
                 tracing::info!(
-                    "FLAC not available, falling back to MP3 for music_id {}",
+                    "Using {} quality for music_id {}",
+                    quality_label(level),
                     music_id
                 );
-                match state.music_api.get_song_url(music_id, 320_000).await {
-                    Ok(url) => url,
-                    Err(e) => {
-                        bot.edit_message_text(
-                            msg.chat.id,
-                            status_msg.id,
-                            format!("❌ 获取下载链接失败: {e}"),
-                        )
-                        .await?;
-                        return Ok(());
-                    }
-                }
-            }
-        }
-    } else {
-        // Get best available MP3 quality
-        match state.music_api.get_song_url(music_id, 320_000).await {
-            Ok(url) => url,
-            Err(_) => {
-                // Try lower quality as fallback
-                match state.music_api.get_song_url(music_id, 128_000).await {
-                    Ok(url) => url,
-                    Err(e) => {
-                        bot.edit_message_text(
-                            msg.chat.id,
-                            status_msg.id,
-                            format!("❌ 获取下载链接失败: {e}"),
-                        )
-                        .await?;
-                        return Ok(());
-                    }
-                }
+                song_url = Some((level, url));
+                break;
             }
+            Err(e) => last_error = Some(e),
         }
+    }
+    if api.user_logged_out() {
+        forget_user_account(bot, state, target.from_user_id).await;
+    }
+
+    let Some((level, song_url)) = song_url else {
+        let text = if let Some(size) = oversized {
+            format!(
+                "❌ 文件过大 ({})，超过上传限制 ({})",
+                format_file_size(size),
+                format_file_size(upload_limit)
+            )
+        } else if let Some(e) = last_error {
+            format!("❌ 获取下载链接失败: {e}")
+        } else {
+            "❌ 无法获取下载链接，可能需要VIP权限".to_string()
+        };
+        target.set_status(bot, state, text).await?;
+        return Ok(false);
     };
 
-    if song_url.url.is_empty() {
-        bot.edit_message_text(
-            msg.chat.id,
-            status_msg.id,
-            "❌ 无法获取下载链接，可能需要VIP权限",
+    // Tell the user when a better quality had to be skipped
+    let quality_note = oversized.map(|size| {
+        format!(
+            "⚠️ 原音质文件 ({}) 超过上传限制 ({})，已自动降级为 {}",
+            format_file_size(size),
+            format_file_size(upload_limit),
+            quality_label(level)
         )
-        .await?;
-        return Ok(());
-    }
+    });
 
     // Update status
     let artists = format_artists(song_detail.ar.as_deref().unwrap_or(&[]));
-    bot.edit_message_text(
-        msg.chat.id,
-        status_msg.id,
-        format!("📥 正在下载: {} - {}", song_detail.name, artists),
-    )
-    .await?;
+    target
+        .set_status(
+            bot,
+            state,
+            format!("📥 正在下载: {} - {}", song_detail.name, artists),
+        )
+        .await?;
 
     // Download and process the song
-    match download_and_send_music(bot, msg, state, &song_detail, &song_url, &status_msg).await {
-        Ok(()) => {
-            // Delete status message
-            bot.delete_message(msg.chat.id, status_msg.id).await.ok();
-        }
+    match download_and_send_music(
+        bot,
+        state,
+        &song_detail,
+        &song_url,
+        quality_note.as_deref(),
+        quality.is_none(),
+        target,
+    )
+    .await
+    {
+        Ok(delivered) => Ok(delivered),
         Err(e) => {
-            bot.edit_message_text(msg.chat.id, status_msg.id, format!("❌ 处理失败: {e}"))
+            target
+                .set_status(bot, state, format!("❌ 处理失败: {e}"))
                 .await?;
+            Ok(false)
         }
     }
-
-    Ok(())
 }
 
 async fn download_and_send_music(
     bot: &Bot,
-    msg: &Message,
     state: &Arc<BotState>,
     song_detail: &crate::music_api::SongDetail,
     song_url: &crate::music_api::SongUrl,
-    status_msg: &Message,
-) -> Result<()> {
+    quality_note: Option<&str>,
+    save: bool,
+    target: &SendTarget,
+) -> Result<bool> {
     let _permit = state.download_semaphore.acquire().await.unwrap();
 
     // Determine file extension
@@ -679,20 +1238,41 @@ async fn download_and_send_music(
 
     if actual_size == 0 {
         audio_buffer.cleanup().await.ok();
-        bot.edit_message_text(msg.chat.id, status_msg.id, "下载失败: 文件为空")
-            .await?;
-        return Ok(());
+        target.set_status(bot, state, "下载失败: 文件为空").await?;
+        return Ok(false);
     }
 
     if actual_size < 1024 {
         audio_buffer.cleanup().await.ok();
-        bot.edit_message_text(
-            msg.chat.id,
-            status_msg.id,
-            format!("下载失败: 文件太小({actual_size} bytes)"),
-        )
-        .await?;
-        return Ok(());
+        target
+            .set_status(
+                bot,
+                state,
+                format!("下载失败: 文件太小({actual_size} bytes)"),
+            )
+            .await?;
+        return Ok(false);
+    }
+
+    // The reported size may differ from what was actually served
+    let upload_limit = state.endpoints.upload_limit();
+    if actual_size > upload_limit {
+        audio_buffer.cleanup().await.ok();
+        if let Some(thumb_buf) = thumbnail_buffer {
+            thumb_buf.cleanup().await.ok();
+        }
+        target
+            .set_status(
+                bot,
+                state,
+                format!(
+                    "下载失败: 文件过大 ({})，超过上传限制 ({})",
+                    format_file_size(actual_size),
+                    format_file_size(upload_limit)
+                ),
+            )
+            .await?;
+        return Ok(false);
     }
 
     tracing::info!("File validation passed: {} bytes", actual_size);
@@ -745,13 +1325,10 @@ async fn download_and_send_music(
         duration: (song_detail.dt.unwrap_or(0) / 1000) as i64,
         file_id: None,
         thumb_file_id: None,
-        from_user_id: msg.from().map_or(0, |u| u.id.0 as i64),
-        from_user_name: msg
-            .from()
-            .and_then(|u| u.username.clone())
-            .unwrap_or_default(),
-        from_chat_id: msg.chat.id.0,
-        from_chat_name: msg.chat.username().unwrap_or("").to_string(),
+        from_user_id: target.from_user_id,
+        from_user_name: target.from_user_name.clone(),
+        from_chat_id: target.chat_id.0,
+        from_chat_name: target.from_chat_name.clone(),
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         ..Default::default()
@@ -768,7 +1345,7 @@ async fn download_and_send_music(
     );
 
     // Send the audio file
-    let caption = build_caption(
+    let mut caption = build_caption(
         &song_info.song_name,
         &song_info.song_artists,
         &song_info.song_album,
@@ -777,6 +1354,10 @@ async fn download_and_send_music(
         song_info.bit_rate,
         &state.bot_username,
     );
+    if let Some(note) = quality_note {
+        caption.push('\n');
+        caption.push_str(note);
+    }
 
     let keyboard = create_music_keyboard(
         song_detail.id,
@@ -805,66 +1386,125 @@ async fn download_and_send_music(
         }
     );
 
-    // Build a dedicated upload bot. If a custom API is configured, use it but with an upload-optimized HTTP client.
-    let (upload_bot, used_custom_api) =
-        if !state.config.bot_api.is_empty() && state.config.bot_api != "https://api.telegram.org" {
-            // Normalize API URL (ensure it ends with /bot)
-            let api_url_str = if state.config.bot_api.ends_with("/bot") {
-                state.config.bot_api.clone()
-            } else {
-                format!("{}/bot", state.config.bot_api)
-            };
-
-            let api_url = reqwest::Url::parse(&api_url_str)
-                .unwrap_or_else(|_| reqwest::Url::parse("https://api.telegram.org/bot").unwrap());
-            tracing::info!("Using custom API for upload: {}", api_url);
-
-            // Create a client optimized for multipart uploads
-            let client = reqwest::Client::builder()
-                .use_rustls_tls()
-                .timeout(std::time::Duration::from_secs(300)) // large files need longer timeouts
-                .pool_max_idle_per_host(0)
-                .no_gzip() // avoid gzip interference on multipart boundaries via proxies
-                .user_agent("Go-http-client/2.0")
-                .default_headers(reqwest::header::HeaderMap::new())
-                .build()
-                .unwrap();
-
-            (
-                Bot::with_client(&state.config.bot_token, client).set_api_url(api_url),
-                true,
-            )
-        } else {
-            (bot.clone(), false)
-        };
-
-    // Send audio file with enhanced error handling and proper MIME type
-    tracing::info!(
-        "Sending audio file: {} ({:.2} MB)",
-        audio_buffer.filename(),
-        file_size as f64 / 1024.0 / 1024.0
-    );
-
     // Simple approach: try sending as audio first, fallback to document if needed
     let is_flac = file_ext == "flac";
 
     tracing::info!("File format: {}", if is_flac { "FLAC" } else { "MP3" });
 
-    // Create InputFile from audio buffer
-    let audio_input_file = audio_buffer.to_input_file();
+    // A local Bot API server reads the file straight from disk, so memory buffers
+    // are written into the shared directory first
+    if state.config.local_mode {
+        ensure_dir(state.config.local_dir())?;
+        audio_buffer = audio_buffer
+            .persist_to_dir(state.config.local_dir())
+            .await?;
+    }
+
+    // Try every available endpoint in turn until one accepts the upload
+    let mut uploaded = false;
+    let mut last_error = None;
+    for endpoint in state.endpoints.upload_candidates() {
+        if file_size > endpoint.upload_limit {
+            tracing::info!(
+                "Skipping {} for upload: {} bytes exceeds its limit of {} bytes",
+                endpoint.url,
+                file_size,
+                endpoint.upload_limit
+            );
+            continue;
+        }
+
+        // Send audio file with enhanced error handling and proper MIME type
+        tracing::info!(
+            "Sending audio file via {}: {} ({:.2} MB)",
+            endpoint.url,
+            audio_buffer.filename(),
+            file_size as f64 / 1024.0 / 1024.0
+        );
+
+        match upload_audio(
+            state,
+            endpoint,
+            target,
+            &audio_buffer,
+            thumbnail_buffer.as_ref(),
+            &caption,
+            &keyboard,
+            &song_info,
+        )
+        .await
+        {
+            Ok(file_id) => {
+                song_info.file_id = file_id;
+                uploaded = true;
+                break;
+            }
+            Err(e) => {
+                tracing::error!("Both audio and document send failed via {}", endpoint.url);
+                state.endpoints.report_failure(endpoint, &e);
+                last_error = Some(e);
+            }
+        }
+    }
+
+    if !uploaded {
+        // Cleanup before returning error
+        audio_buffer.cleanup().await.ok();
+        if let Some(thumb_buf) = thumbnail_buffer {
+            thumb_buf.cleanup().await.ok();
+        }
+        let error = last_error.map_or_else(
+            || anyhow::anyhow!("没有可接收该文件大小的 Telegram API"),
+            anyhow::Error::from,
+        );
+        target
+            .set_status(bot, state, format!("发送失败: {error}"))
+            .await
+            .ok();
+        return Err(error.into());
+    }
+
+    // Save to database
+    if save {
+        state.database.save_song_info(&song_info).await?;
+    }
+
+    // Clean up resources
+    audio_buffer.cleanup().await.ok();
+    if let Some(thumb_buf) = thumbnail_buffer {
+        thumb_buf.cleanup().await.ok();
+    }
+
+    Ok(true)
+}
+
+/// Upload the audio through one endpoint: as audio first, then as a document.
+/// Returns the Telegram `file_id` of the sent file.
+#[allow(clippy::too_many_arguments)]
+async fn upload_audio(
+    state: &BotState,
+    endpoint: &ApiEndpoint,
+    target: &SendTarget,
+    audio_buffer: &AudioBuffer,
+    thumbnail_buffer: Option<&ThumbnailBuffer>,
+    caption: &str,
+    keyboard: &InlineKeyboardMarkup,
+    song_info: &SongInfo,
+) -> std::result::Result<Option<String>, teloxide::RequestError> {
+    let upload_bot = endpoint.upload_bot();
 
     // Try sending as audio with basic metadata
     let mut audio_req = upload_bot
-        .send_audio(msg.chat.id, audio_input_file)
-        .caption(&caption)
+        .send_audio(target.chat_id, upload_input_file(endpoint, audio_buffer))
+        .caption(caption)
         .title(&song_info.song_name)
         .performer(&song_info.song_artists)
         .duration(song_info.duration as u32)
-        .reply_markup(keyboard.clone())
-        .reply_to_message_id(msg.id);
+        .reply_markup(keyboard.clone());
+    audio_req.reply_to_message_id = target.reply_to;
 
     // Attach thumbnail if available
-    if let Some(ref thumb_buf) = thumbnail_buffer {
+    if let Some(thumb_buf) = thumbnail_buffer {
         match thumb_buf.to_input_file() {
             Ok(thumb_input) => {
                 audio_req = audio_req.thumb(thumb_input);
@@ -876,117 +1516,50 @@ async fn download_and_send_music(
     }
 
     // Thumbnail will be embedded into tags for MP3 and FLAC (when possible)
-    let audio_result = audio_req.await;
-
-    match audio_result {
+    match state.queue.deliver(target.chat_id, audio_req).await {
         Ok(sent_msg) => {
-            tracing::info!(
-                "Successfully sent as audio: {}",
-                if is_flac { "FLAC" } else { "MP3" }
-            );
+            tracing::info!("Successfully sent as audio");
 
             // Extract file_id from sent message
             if let MessageKind::Common(common) = &sent_msg.kind {
                 if let teloxide::types::MediaKind::Audio(audio) = &common.media_kind {
-                    song_info.file_id = Some(audio.audio.file.id.clone());
+                    return Ok(Some(audio.audio.file.id.clone()));
                 }
             }
+            Ok(None)
         }
         Err(e) => {
             tracing::warn!("Audio send failed: {}, trying document fallback", e);
 
             // Fallback: send as document (need to create InputFile again)
-            let doc_input_file = audio_buffer.to_input_file();
-            let doc_req = upload_bot
-                .send_document(msg.chat.id, doc_input_file)
-                .caption(&caption)
-                .reply_markup(keyboard)
-                .reply_to_message_id(msg.id);
             // For document, Telegram may not show embedded art; we still embed where possible
-            let doc_result = doc_req.await;
-
-            match doc_result {
-                Ok(sent_msg) => {
-                    tracing::info!("Successfully sent as document");
-                    if let MessageKind::Common(common) = &sent_msg.kind {
-                        if let teloxide::types::MediaKind::Document(document) = &common.media_kind {
-                            song_info.file_id = Some(document.document.file.id.clone());
-                        }
-                    }
-                }
-                Err(doc_err) => {
-                    tracing::error!("Both audio and document send failed via custom/primary API");
-                    // If we were using a custom API, try one last fallback using the official API for upload
-                    if used_custom_api {
-                        tracing::warn!("Retrying upload via official Telegram API as fallback");
-                        let official_bot = Bot::new(&state.config.bot_token);
-                        let retry_input_file = audio_buffer.to_input_file();
-                        let retry_req = official_bot
-                            .send_document(msg.chat.id, retry_input_file)
-                            .caption(&caption)
-                            .reply_to_message_id(msg.id);
-                        // retry without explicit thumbnail method
-                        let retry = retry_req.await;
-                        match retry {
-                            Ok(sent_msg) => {
-                                tracing::info!("Upload succeeded via official API fallback");
-                                if let MessageKind::Common(common) = &sent_msg.kind {
-                                    if let teloxide::types::MediaKind::Document(document) =
-                                        &common.media_kind
-                                    {
-                                        song_info.file_id = Some(document.document.file.id.clone());
-                                    }
-                                }
-                            }
-                            Err(final_err) => {
-                                // Cleanup before returning error
-                                audio_buffer.cleanup().await.ok();
-                                if let Some(thumb_buf) = thumbnail_buffer {
-                                    thumb_buf.cleanup().await.ok();
-                                }
-                                bot.edit_message_text(
-                                    msg.chat.id,
-                                    status_msg.id,
-                                    format!("发送失败: {final_err}"),
-                                )
-                                .await
-                                .ok();
-                                return Err(final_err.into());
-                            }
-                        }
-                    } else {
-                        // Cleanup before returning error
-                        audio_buffer.cleanup().await.ok();
-                        if let Some(thumb_buf) = thumbnail_buffer {
-                            thumb_buf.cleanup().await.ok();
-                        }
-                        bot.edit_message_text(
-                            msg.chat.id,
-                            status_msg.id,
-                            format!("发送失败: {doc_err}"),
-                        )
-                        .await
-                        .ok();
-                        return Err(doc_err.into());
-                    }
+            let mut document_req = upload_bot
+                .send_document(target.chat_id, upload_input_file(endpoint, audio_buffer))
+                .caption(caption)
+                .reply_markup(keyboard.clone());
+            document_req.reply_to_message_id = target.reply_to;
+            let sent_msg = state.queue.deliver(target.chat_id, document_req).await?;
+
+            tracing::info!("Successfully sent as document");
+            if let MessageKind::Common(common) = &sent_msg.kind {
+                if let teloxide::types::MediaKind::Document(document) = &common.media_kind {
+                    return Ok(Some(document.document.file.id.clone()));
                 }
             }
+            Ok(None)
         }
     }
+}
 
-    // Save to database
-    state.database.save_song_info(&song_info).await?;
-
-    // Clean up resources
-    audio_buffer.cleanup().await.ok();
-    if let Some(thumb_buf) = thumbnail_buffer {
-        thumb_buf.cleanup().await.ok();
+/// Build the upload InputFile, using a `file://` path when talking to a local Bot API server
+fn upload_input_file(endpoint: &ApiEndpoint, audio_buffer: &AudioBuffer) -> InputFile {
+    if endpoint.local {
+        match audio_buffer.to_local_input_file() {
+            Ok(input_file) => return input_file,
+            Err(e) => tracing::warn!("Local upload unavailable, using multipart: {}", e),
+        }
     }
-
-    // Delete status message
-    bot.delete_message(msg.chat.id, status_msg.id).await.ok();
-
-    Ok(())
+    audio_buffer.to_input_file()
 }
 
 fn create_music_keyboard(music_id: u64, song_name: &str, artists: &str) -> InlineKeyboardMarkup {
@@ -995,167 +1568,1463 @@ fn create_music_keyboard(music_id: u64, song_name: &str, artists: &str) -> Inlin
             format!("{song_name} - {artists}"),
             reqwest::Url::parse(&format!("https://music.163.com/song?id={music_id}")).unwrap(),
         )],
-        vec![InlineKeyboardButton::switch_inline_query(
-            "分享给朋友",
-            format!("https://music.163.com/song?id={music_id}"),
-        )],
+        vec![
+            InlineKeyboardButton::callback("歌词", format!("lyric {music_id}")),
+            InlineKeyboardButton::callback("热评", format!("comments {music_id}")),
+            InlineKeyboardButton::callback("相似歌曲", format!("similar {music_id}")),
+            InlineKeyboardButton::callback("专辑", format!("songalbum {music_id}")),
+        ],
+        vec![
+            InlineKeyboardButton::callback("ℹ️ 详情", format!("info {music_id}")),
+            InlineKeyboardButton::callback("📻 电台", format!("radio {music_id}")),
+            InlineKeyboardButton::switch_inline_query(
+                "分享给朋友",
+                format!("https://music.163.com/song?id={music_id}"),
+            ),
+        ],
     ])
 }
 
-async fn handle_music_url(
+/// Dispatch a parsed NetEase link to the matching handler
+async fn handle_netease_link(
     bot: &Bot,
     msg: &Message,
     state: &Arc<BotState>,
-    text: &str,
+    link: NeteaseLink,
 ) -> ResponseResult<()> {
-    if let Some(music_id) = parse_music_id(text) {
-        process_music(bot, msg, state, music_id).await
-    } else {
-        bot.send_message(msg.chat.id, "无法从链接中提取音乐ID")
-            .reply_to_message_id(msg.id)
-            .await?;
-        Ok(())
+    match link {
+        NeteaseLink::Song(music_id) => {
+            process_music(bot, msg, state, msg.from(), music_id, None).await
+        }
+        NeteaseLink::DjProgram(program_id) => {
+            match state.music_api.get_dj_program_song_id(program_id).await {
+                Ok(music_id) => process_music(bot, msg, state, msg.from(), music_id, None).await,
+                Err(e) => {
+                    state
+                        .queue
+                        .deliver(
+                            msg.chat.id,
+                            bot.send_message(msg.chat.id, format!("获取电台节目失败: {e}"))
+                                .reply_to_message_id(msg.id),
+                        )
+                        .await?;
+                    Ok(())
+                }
+            }
+        }
+        NeteaseLink::Album(_) | NeteaseLink::Playlist(_) | NeteaseLink::Artist(_) => {
+            show_collection(bot, msg, state, link).await
+        }
+        NeteaseLink::Mv(_) | NeteaseLink::User(_) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("暂不支持解析{}链接", link.kind_name()))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            Ok(())
+        }
     }
 }
 
-async fn handle_search_command(
+async fn handle_music_url(
     bot: &Bot,
     msg: &Message,
     state: &Arc<BotState>,
-    args: Option<String>,
+    text: &str,
 ) -> ResponseResult<()> {
-    let keyword = match args {
-        Some(kw) if !kw.is_empty() => kw,
-        _ => {
-            bot.send_message(msg.chat.id, "请输入搜索关键词")
-                .reply_to_message_id(msg.id)
-                .await?;
-            return Ok(());
+    if let Some(link) = NeteaseLink::parse(text) {
+        return handle_netease_link(bot, msg, state, link).await;
+    }
+
+    let error_text = if let Some(short_url) = find_short_links(text).into_iter().next() {
+        match state.music_api.resolve_short_link(&short_url).await {
+            Ok(link) => return handle_netease_link(bot, msg, state, link).await,
+            Err(e) => {
+                tracing::warn!("Failed to resolve short link {}: {}", short_url, e);
+                "无法解析分享短链接，请发送完整的网易云音乐链接"
+            }
         }
+    } else {
+        "无法从链接中提取音乐ID"
     };
 
-    let search_msg = bot
-        .send_message(msg.chat.id, "🔍 搜索中...")
-        .reply_to_message_id(msg.id)
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, error_text)
+                .reply_to_message_id(msg.id),
+        )
         .await?;
+    Ok(())
+}
 
-    match state.music_api.search_songs(&keyword, 10).await {
-        Ok(songs) => {
-            if songs.is_empty() {
-                bot.edit_message_text(msg.chat.id, search_msg.id, "未找到相关歌曲")
-                    .await?;
-                return Ok(());
-            }
+/// Results shown per search result page
+const SEARCH_PAGE_SIZE: u32 = 8;
+/// Tracks listed for an album, artist or playlist
+const COLLECTION_TRACK_LIMIT: usize = 20;
 
-            let mut results = String::new();
-            let mut buttons = Vec::new();
+/// Short stable token for a search, so page buttons fit into callback data
+fn search_token(search_type: SearchType, keyword: &str) -> String {
+    let digest = md5::compute(format!("{}:{keyword}", search_type.code()));
+    format!("{digest:x}")[..12].to_string()
+}
+
+/// Fetch one page of search results, served from the short-lived cache when possible
+async fn load_search_page(
+    state: &Arc<BotState>,
+    search_type: SearchType,
+    keyword: &str,
+    page: u32,
+) -> Result<Arc<SearchResult>> {
+    let key = (search_type, keyword.to_string(), page);
+    if let Some(result) = state.search_pages.get(&key) {
+        return Ok(result);
+    }
 
-            for (i, song) in songs.iter().take(8).enumerate() {
-                let artists = format_artists(&song.artists);
-                results.push_str(&format!("{}.「{}」 - {}\n", i + 1, song.name, artists));
+    let result = Arc::new(
+        state
+            .music_api
+            .search(
+                keyword,
+                search_type,
+                SEARCH_PAGE_SIZE,
+                page * SEARCH_PAGE_SIZE,
+            )
+            .await?,
+    );
+    state.search_pages.insert(key, Arc::clone(&result));
+    Ok(result)
+}
+
+/// Render a search result page with one button per result and page navigation
+fn render_search_page(
+    search_type: SearchType,
+    keyword: &str,
+    token: &str,
+    page: u32,
+    result: &SearchResult,
+) -> (String, InlineKeyboardMarkup) {
+    let total = result.total(search_type);
+    let total_pages = total.div_ceil(u64::from(SEARCH_PAGE_SIZE)).max(1);
+    let offset = page * SEARCH_PAGE_SIZE;
+
+    let mut text = format!(
+        "🔍 {}「{keyword}」共 {total} 个结果，第 {}/{} 页\n\n",
+        search_type.name(),
+        page + 1,
+        total_pages
+    );
+    let mut buttons = Vec::new();
+
+    match search_type {
+        SearchType::Song | SearchType::Lyric => {
+            for (i, song) in (offset + 1..).zip(&result.songs) {
+                let marker = song
+                    .fee_label()
+                    .map(|label| format!(" [{label}]"))
+                    .unwrap_or_default();
+                text.push_str(&format!(
+                    "{i}.「{}」 - {}{marker}\n    💿 {} · ⏱ {}\n",
+                    song.name,
+                    format_artists(&song.artists),
+                    song.album.name,
+                    format_duration(song.duration / 1000)
+                ));
+                if search_type == SearchType::Lyric {
+                    if let Some(snippet) = song.lyric_snippet(keyword) {
+                        text.push_str(&format!("    🎤 {snippet}\n"));
+                    }
+                }
                 buttons.push(InlineKeyboardButton::callback(
-                    format!("{}", i + 1),
+                    i.to_string(),
                     format!("music {}", song.id),
                 ));
             }
+        }
+        SearchType::Album => {
+            for (i, album) in (offset + 1..).zip(&result.albums) {
+                text.push_str(&format!(
+                    "{i}.「{}」 - {}\n    🎵 {} 首\n",
+                    album.name,
+                    format_artists(&album.artists),
+                    album.size
+                ));
+                buttons.push(InlineKeyboardButton::callback(
+                    i.to_string(),
+                    format!("album {}", album.id),
+                ));
+            }
+        }
+        SearchType::Artist => {
+            for (i, artist) in (offset + 1..).zip(&result.artists) {
+                let alias = if artist.alias.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", artist.alias.join("/"))
+                };
+                text.push_str(&format!(
+                    "{i}. {}{alias}\n    💿 {} 张专辑\n",
+                    artist.name, artist.album_size
+                ));
+                buttons.push(InlineKeyboardButton::callback(
+                    i.to_string(),
+                    format!("artist {}", artist.id),
+                ));
+            }
+        }
+        SearchType::Playlist => {
+            for (i, playlist) in (offset + 1..).zip(&result.playlists) {
+                let creator = playlist
+                    .creator
+                    .as_ref()
+                    .map(|creator| format!(" by {}", creator.nickname))
+                    .unwrap_or_default();
+                text.push_str(&format!(
+                    "{i}.「{}」{creator}\n    🎵 {} 首 · ▶️ {} 次播放\n",
+                    playlist.name, playlist.track_count, playlist.play_count
+                ));
+                buttons.push(InlineKeyboardButton::callback(
+                    i.to_string(),
+                    format!("playlist {}", playlist.id),
+                ));
+            }
+        }
+        SearchType::User => {
+            for (i, user) in (offset + 1..).zip(&result.userprofiles) {
+                text.push_str(&format!("{i}. {}\n", user.nickname));
+                if let Some(signature) = user.signature.as_deref().filter(|s| !s.is_empty()) {
+                    text.push_str(&format!("    {}\n", signature.replace('\n', " ")));
+                }
+                let url = NeteaseLink::User(user.user_id).url();
+                buttons.push(InlineKeyboardButton::url(
+                    i.to_string(),
+                    reqwest::Url::parse(&url).unwrap(),
+                ));
+            }
+        }
+        SearchType::Mv => {
+            for (i, mv) in (offset + 1..).zip(&result.mvs) {
+                text.push_str(&format!(
+                    "{i}.「{}」 - {}\n    ⏱ {}\n",
+                    mv.name,
+                    mv.artist_name,
+                    format_duration(mv.duration / 1000)
+                ));
+                let url = NeteaseLink::Mv(mv.id).url();
+                buttons.push(InlineKeyboardButton::url(
+                    i.to_string(),
+                    reqwest::Url::parse(&url).unwrap(),
+                ));
+            }
+        }
+    }
 
-            let keyboard = InlineKeyboardMarkup::new(vec![buttons]);
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = buttons
+        .chunks(4)
+        .map(<[InlineKeyboardButton]>::to_vec)
+        .collect();
+
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(
+            "⬅️ 上一页",
+            format!("search {token} {}", page - 1),
+        ));
+    }
+    if u64::from(page) + 1 < total_pages {
+        navigation.push(InlineKeyboardButton::callback(
+            "下一页 ➡️",
+            format!("search {token} {}", page + 1),
+        ));
+    }
+    if !navigation.is_empty() {
+        rows.push(navigation);
+    }
 
-            bot.edit_message_text(msg.chat.id, search_msg.id, results)
-                .reply_markup(keyboard)
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
+/// Flip a search result message to another page
+async fn handle_search_page_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    state: &Arc<BotState>,
+    token: &str,
+    page: u32,
+) -> ResponseResult<()> {
+    let Some(msg) = query.message.as_ref() else {
+        bot.answer_callback_query(&query.id).await?;
+        return Ok(());
+    };
+    let Some((search_type, keyword)) = state.search_queries.get(&token.to_string()) else {
+        bot.answer_callback_query(&query.id)
+            .text("搜索已过期，请重新搜索")
+            .await?;
+        return Ok(());
+    };
+
+    match load_search_page(state, search_type, &keyword, page).await {
+        Ok(result) if result.len(search_type) > 0 => {
+            let (text, keyboard) = render_search_page(search_type, &keyword, token, page, &result);
+            bot.answer_callback_query(&query.id).await?;
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.edit_message_text(msg.chat.id, msg.id, text)
+                        .reply_markup(keyboard),
+                )
+                .await?;
+        }
+        Ok(_) => {
+            bot.answer_callback_query(&query.id)
+                .text("没有更多结果了")
                 .await?;
         }
         Err(e) => {
-            bot.edit_message_text(msg.chat.id, search_msg.id, format!("搜索失败: {e}"))
+            bot.answer_callback_query(&query.id)
+                .text(format!("❌ 搜索失败: {e}"))
                 .await?;
         }
     }
-
     Ok(())
 }
 
-async fn handle_about_command(
+async fn handle_search_command(
     bot: &Bot,
     msg: &Message,
-    _state: &Arc<BotState>,
+    state: &Arc<BotState>,
+    args: Option<String>,
 ) -> ResponseResult<()> {
-    let about_text = format!(
-        r"🎵 Music163bot-Rust v{}
-
-一个用来下载/分享/搜索网易云歌曲的 Telegram Bot
+    let args = args.unwrap_or_default();
+    let (search_type, keyword) = SearchType::parse_query(&args);
+    if keyword.is_empty() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(
+                    msg.chat.id,
+                    "请输入搜索关键词\n\n\
+                    可以使用前缀搜索其他类型，例如:\n\
+                    /search album: 范特西\n\
+                    /search artist: 周杰伦\n\
+                    /search playlist: 华语经典\n\
+                    /search lyric: 后来 我总算学会了\n\
+                    /search user: 昵称\n\
+                    /search mv: 晴天",
+                )
+                .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
 
-特性：
-• 🔗 分享链接嗅探
-• 🎵 歌曲搜索与下载
-• 💾 智能缓存系统
-• 🚀 智能存储 (v1.1.0+)
-• 🎤 歌词获取
-• 📊 使用统计
+    let search_msg = state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, "🔍 搜索中...")
+                .reply_to_message_id(msg.id),
+        )
+        .await?;
 
-技术栈：
-• 🦀 Rust + Teloxide
-• 🔧 高并发处理
-• 📦 轻量级部署
+    let token = search_token(search_type, keyword);
+    state
+        .search_queries
+        .insert(token.clone(), (search_type, keyword.to_string()));
 
-源码：GitHub | 原版：Music163bot-Go",
-        env!("CARGO_PKG_VERSION")
-    );
+    let (text, keyboard) = match load_search_page(state, search_type, keyword, 0).await {
+        Ok(result) if result.len(search_type) == 0 => {
+            (format!("未找到相关{}", search_type.name()), None)
+        }
+        Ok(result) => {
+            let (text, keyboard) = render_search_page(search_type, keyword, &token, 0, &result);
+            (text, Some(keyboard))
+        }
+        Err(e) => (format!("搜索失败: {e}"), None),
+    };
 
-    bot.send_message(msg.chat.id, about_text)
-        .reply_to_message_id(msg.id)
-        .disable_web_page_preview(true)
-        .await?;
+    let mut edit = bot.edit_message_text(msg.chat.id, search_msg.id, text);
+    edit.reply_markup = keyboard;
+    state.queue.deliver(msg.chat.id, edit).await?;
 
     Ok(())
 }
 
-async fn handle_lyric_command(
+/// Fetch the title, total track count and tracks of an album, artist or playlist
+async fn load_collection(
+    state: &Arc<BotState>,
+    link: NeteaseLink,
+) -> Result<(String, u64, Vec<SongDetail>)> {
+    match link {
+        NeteaseLink::Album(id) => {
+            let data = state.music_api.get_album(id).await?;
+            let title = format!(
+                "💿 专辑「{}」 - {}",
+                data.album.name,
+                format_artists(&data.album.artists)
+            );
+            let total = data.album.size.max(data.songs.len() as u64);
+            Ok((title, total, data.songs))
+        }
+        NeteaseLink::Artist(id) => {
+            let data = state.music_api.get_artist(id).await?;
+            let title = format!("🎤 歌手「{}」的热门歌曲", data.artist.name);
+            Ok((title, data.hot_songs.len() as u64, data.hot_songs))
+        }
+        NeteaseLink::Playlist(id) => {
+            let playlist = state.music_api.get_playlist(id).await?;
+            let creator = playlist
+                .creator
+                .as_ref()
+                .map(|creator| format!(" by {}", creator.nickname))
+                .unwrap_or_default();
+            let title = format!("📜 歌单「{}」{creator}", playlist.name);
+            let total = playlist.track_count.max(playlist.tracks.len() as u64);
+            Ok((title, total, playlist.tracks))
+        }
+        _ => Err(BotError::Parse(format!(
+            "{} is not a track collection",
+            link.kind_name()
+        ))),
+    }
+}
+
+/// List the tracks of an album, artist or playlist with a download button per track
+async fn show_collection(
     bot: &Bot,
     msg: &Message,
     state: &Arc<BotState>,
-    args: Option<String>,
+    link: NeteaseLink,
 ) -> ResponseResult<()> {
-    let args = args.unwrap_or_default();
-
-    if args.is_empty() {
-        bot.send_message(msg.chat.id, "请输入歌曲ID或关键词")
-            .reply_to_message_id(msg.id)
-            .await?;
-        return Ok(());
-    }
+    let status_msg = state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, format!("🔍 正在获取{}...", link.kind_name()))
+                .reply_to_message_id(msg.id),
+        )
+        .await?;
 
-    let music_id = if let Some(id) = parse_music_id(&args) {
-        id
-    } else {
-        match state.music_api.search_songs(&args, 1).await {
-            Ok(songs) => {
-                if let Some(song) = songs.first() {
-                    song.id
-                } else {
-                    bot.send_message(msg.chat.id, "未找到相关歌曲")
-                        .reply_to_message_id(msg.id)
-                        .await?;
-                    return Ok(());
-                }
+    let (title, total, tracks) = match load_collection(state, link).await {
+        Ok(collection) if !collection.2.is_empty() => collection,
+        Ok(_) => {
+            state
+                .queue
+                .progress(
+                    msg.chat.id,
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        status_msg.id,
+                        format!("该{}中没有可用的歌曲", link.kind_name()),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            state
+                .queue
+                .progress(
+                    msg.chat.id,
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        status_msg.id,
+                        format!("获取{}失败: {e}", link.kind_name()),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut text = format!("{title}\n共 {total} 首");
+    if tracks.len() > COLLECTION_TRACK_LIMIT {
+        text.push_str(&format!("，仅显示前 {COLLECTION_TRACK_LIMIT} 首"));
+    }
+    text.push_str("\n\n");
+
+    let mut buttons = Vec::new();
+    for (i, track) in (1..).zip(tracks.iter().take(COLLECTION_TRACK_LIMIT)) {
+        let artists = track.ar.as_deref().map(format_artists).unwrap_or_default();
+        let duration = track
+            .dt
+            .map(|dt| format!(" · {}", format_duration(dt / 1000)))
+            .unwrap_or_default();
+        text.push_str(&format!("{i}.「{}」 - {artists}{duration}\n", track.name));
+        buttons.push(InlineKeyboardButton::callback(
+            i.to_string(),
+            format!("music {}", track.id),
+        ));
+    }
+
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = buttons
+        .chunks(5)
+        .map(<[InlineKeyboardButton]>::to_vec)
+        .collect();
+    rows.push(vec![InlineKeyboardButton::url(
+        "🔗 在网易云音乐中打开",
+        reqwest::Url::parse(&link.url()).unwrap(),
+    )]);
+
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.edit_message_text(msg.chat.id, status_msg.id, text)
+                .reply_markup(InlineKeyboardMarkup::new(rows)),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Comments shown per page
+const COMMENT_PAGE_SIZE: u32 = 5;
+/// Similar songs listed for a song
+const SIMILAR_SONG_LIMIT: usize = 8;
+
+/// One comment with its author, likes, date and the comment it replies to
+fn format_comment(comment: &Comment) -> String {
+    let date = chrono::DateTime::from_timestamp_millis(comment.time)
+        .map(|time| format!(" · {}", time.format("%Y-%m-%d")))
+        .unwrap_or_default();
+    let mut text = format!(
+        "👤 {} · 👍 {}{date}\n{}\n",
+        comment.user.nickname,
+        comment.liked_count,
+        truncate_chars(&comment.content, 300)
+    );
+    for replied in comment.be_replied.iter().flatten() {
+        let content = replied.content.as_deref().unwrap_or("该评论已删除");
+        text.push_str(&format!(
+            "    ↪ @{}: {}\n",
+            replied.user.nickname,
+            truncate_chars(content, 100)
+        ));
+    }
+    text
+}
+
+/// `/comments <id|link|关键词>`: browse the comments of a song
+async fn handle_comments_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let args = args.unwrap_or_default();
+
+    if args.is_empty() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "请输入歌曲ID、链接或关键词")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let music_id = if let Some(id) = parse_music_id(&args) {
+        id
+    } else {
+        match resolve_song_query(bot, msg, state, &args, "comments").await? {
+            Some(music_id) => music_id,
+            None => return Ok(()),
+        }
+    };
+
+    show_comments(bot, msg, state, music_id).await
+}
+
+/// Fetch one page of comments together with the song name for the title
+async fn load_comments(
+    state: &Arc<BotState>,
+    music_id: u64,
+    sort: CommentSort,
+    page: u32,
+) -> Result<(String, CommentPage)> {
+    let (detail, comments) = tokio::join!(
+        state.music_api.get_song_detail(music_id),
+        state
+            .music_api
+            .get_comments(music_id, sort, COMMENT_PAGE_SIZE, page * COMMENT_PAGE_SIZE)
+    );
+    let name = detail.map_or_else(|_| music_id.to_string(), |detail| detail.name);
+    Ok((name, comments?))
+}
+
+/// Render a comment page with page navigation and a switch between hot and latest
+fn render_comments(
+    song_name: &str,
+    music_id: u64,
+    sort: CommentSort,
+    page: u32,
+    comments: &CommentPage,
+) -> (String, InlineKeyboardMarkup) {
+    let total_pages = comments
+        .total
+        .div_ceil(u64::from(COMMENT_PAGE_SIZE))
+        .max(u64::from(page) + 1);
+    let mut text = format!(
+        "💬「{song_name}」的{} · 共 {} 条 · 第 {}/{} 页\n\n",
+        sort.name(),
+        comments.total,
+        page + 1,
+        total_pages
+    );
+    for comment in &comments.comments {
+        text.push_str(&format_comment(comment));
+        text.push('\n');
+    }
+
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(
+            "⬅️ 上一页",
+            format!("cpage {music_id} {} {}", sort.code(), page - 1),
+        ));
+    }
+    if comments.has_more {
+        navigation.push(InlineKeyboardButton::callback(
+            "下一页 ➡️",
+            format!("cpage {music_id} {} {}", sort.code(), page + 1),
+        ));
+    }
+    let other = match sort {
+        CommentSort::Hot => CommentSort::Latest,
+        CommentSort::Latest => CommentSort::Hot,
+    };
+    let mut rows = Vec::new();
+    if !navigation.is_empty() {
+        rows.push(navigation);
+    }
+    rows.push(vec![InlineKeyboardButton::callback(
+        format!("切换到{}", other.name()),
+        format!("cpage {music_id} {} 0", other.code()),
+    )]);
+
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
+/// Reply with the first page of hot comments, or the latest ones when there are none
+async fn show_comments(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    music_id: u64,
+) -> ResponseResult<()> {
+    let mut loaded = load_comments(state, music_id, CommentSort::Hot, 0)
+        .await
+        .map(|(name, comments)| (CommentSort::Hot, name, comments));
+    if matches!(&loaded, Ok((_, _, comments)) if comments.comments.is_empty()) {
+        loaded = load_comments(state, music_id, CommentSort::Latest, 0)
+            .await
+            .map(|(name, comments)| (CommentSort::Latest, name, comments));
+    }
+
+    let (text, keyboard) = match loaded {
+        Ok((_, _, comments)) if comments.comments.is_empty() => {
+            ("该歌曲暂无评论".to_string(), None)
+        }
+        Ok((sort, name, comments)) => {
+            let (text, keyboard) = render_comments(&name, music_id, sort, 0, &comments);
+            (text, Some(keyboard))
+        }
+        Err(e) => (format!("获取评论失败: {e}"), None),
+    };
+
+    let mut request = bot
+        .send_message(msg.chat.id, text)
+        .reply_to_message_id(msg.id);
+    request.reply_markup = keyboard.map(ReplyMarkup::InlineKeyboard);
+    state.queue.deliver(msg.chat.id, request).await?;
+    Ok(())
+}
+
+/// Flip a comment message to another page or sort order
+async fn handle_comment_page_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    state: &Arc<BotState>,
+    music_id: u64,
+    sort: CommentSort,
+    page: u32,
+) -> ResponseResult<()> {
+    let Some(msg) = query.message.as_ref() else {
+        bot.answer_callback_query(&query.id).await?;
+        return Ok(());
+    };
+
+    match load_comments(state, music_id, sort, page).await {
+        Ok((_, comments)) if comments.comments.is_empty() => {
+            bot.answer_callback_query(&query.id)
+                .text(format!("没有更多{}了", sort.name()))
+                .await?;
+        }
+        Ok((name, comments)) => {
+            let (text, keyboard) = render_comments(&name, music_id, sort, page, &comments);
+            bot.answer_callback_query(&query.id).await?;
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.edit_message_text(msg.chat.id, msg.id, text)
+                        .reply_markup(keyboard),
+                )
+                .await?;
+        }
+        Err(e) => {
+            bot.answer_callback_query(&query.id)
+                .text(format!("❌ 获取评论失败: {e}"))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reply with songs similar to a song, with a download button per song
+async fn show_similar_songs(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    music_id: u64,
+) -> ResponseResult<()> {
+    let songs = match state.music_api.get_similar_songs(music_id).await {
+        Ok(songs) if songs.is_empty() => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, "没有找到相似歌曲")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(());
+        }
+        Ok(songs) => songs,
+        Err(e) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("获取相似歌曲失败: {e}"))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut text = String::from("🎧 相似歌曲\n\n");
+    let mut buttons = Vec::new();
+    for (i, song) in (1..).zip(songs.iter().take(SIMILAR_SONG_LIMIT)) {
+        text.push_str(&format!(
+            "{i}.「{}」 - {}\n    💿 {} · ⏱ {}\n",
+            song.name,
+            format_artists(&song.artists),
+            song.album.name,
+            format_duration(song.duration / 1000)
+        ));
+        buttons.push(InlineKeyboardButton::callback(
+            i.to_string(),
+            format!("music {}", song.id),
+        ));
+    }
+    let rows: Vec<Vec<InlineKeyboardButton>> = buttons
+        .chunks(4)
+        .map(<[InlineKeyboardButton]>::to_vec)
+        .collect();
+
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(InlineKeyboardMarkup::new(rows))
+                .reply_to_message_id(msg.id),
+        )
+        .await?;
+    Ok(())
+}
+
+/// `/similar <id|link|关键词>`: list songs similar to a song
+async fn handle_similar_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let args = args.unwrap_or_default();
+
+    if args.is_empty() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "请输入歌曲ID、链接或关键词")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let music_id = if let Some(id) = parse_music_id(&args) {
+        id
+    } else {
+        match resolve_song_query(bot, msg, state, &args, "similar").await? {
+            Some(music_id) => music_id,
+            None => return Ok(()),
+        }
+    };
+
+    show_similar_songs(bot, msg, state, music_id).await
+}
+
+/// Recently played radio tracks whose similar songs are searched for the next track
+const RADIO_LOOKBACK: usize = 3;
+
+/// `/radio <id|link|关键词>`: play a song, then keep offering similar ones
+async fn handle_radio_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let args = args.unwrap_or_default();
+
+    if args.is_empty() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "请输入歌曲ID、链接或关键词作为电台的第一首歌")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let music_id = if let Some(id) = parse_music_id(&args) {
+        id
+    } else {
+        match resolve_song_query(bot, msg, state, &args, "radio").await? {
+            Some(music_id) => music_id,
+            None => return Ok(()),
+        }
+    };
+
+    start_radio(bot, msg, state, msg.from(), music_id).await
+}
+
+/// Start a radio session for `from` with `music_id` as its first track
+async fn start_radio(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    from: Option<&User>,
+    music_id: u64,
+) -> ResponseResult<()> {
+    let Some(session_id) = create_radio_session(bot, msg, state, music_id).await? else {
+        return Ok(());
+    };
+    // The controls stay usable even if the first song fails
+    let result = process_music(bot, msg, state, from, music_id, None).await;
+    send_radio_controls(bot, msg, state, session_id, 1).await?;
+    result
+}
+
+/// Start a radio session from the song `msg` carries, which is already in the chat
+async fn start_radio_below(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    music_id: u64,
+) -> ResponseResult<()> {
+    let Some(session_id) = create_radio_session(bot, msg, state, music_id).await? else {
+        return Ok(());
+    };
+    send_radio_controls(bot, msg, state, session_id, 1).await
+}
+
+/// Create a radio session in the chat of `msg`, reporting failures there
+async fn create_radio_session(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    music_id: u64,
+) -> ResponseResult<Option<i64>> {
+    match state
+        .database
+        .create_radio_session(msg.chat.id.0, music_id as i64)
+        .await
+    {
+        Ok(session_id) => Ok(Some(session_id)),
+        Err(e) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("开启电台失败: {e}"))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Post the radio buttons below the latest track
+async fn send_radio_controls(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    session_id: i64,
+    played: usize,
+) -> ResponseResult<()> {
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback("⏭ 下一首", format!("radionext {session_id}")),
+        InlineKeyboardButton::callback("⏹ 停止", format!("radiostop {session_id}")),
+    ]]);
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(
+                msg.chat.id,
+                format!("📻 相似歌曲电台 · 已播放 {played} 首\n点击「下一首」继续播放相似歌曲"),
+            )
+            .reply_markup(keyboard)
+            .reply_to_message_id(msg.id),
+        )
+        .await?;
+    Ok(())
+}
+
+/// The first song similar to a recent track that this session has not played yet
+async fn next_radio_track(state: &Arc<BotState>, played: &[i64]) -> Result<Option<u64>> {
+    let seen: HashSet<i64> = played.iter().copied().collect();
+    for &recent in played.iter().take(RADIO_LOOKBACK) {
+        let songs = state.music_api.get_similar_songs(recent as u64).await?;
+        if let Some(song) = songs.iter().find(|song| !seen.contains(&(song.id as i64))) {
+            return Ok(Some(song.id));
+        }
+    }
+    Ok(None)
+}
+
+/// Play the next radio track below the pressed controls, then move the controls down
+async fn handle_radio_next_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    state: &Arc<BotState>,
+    session_id: i64,
+) -> ResponseResult<()> {
+    let Some(msg) = query.message.as_ref() else {
+        bot.answer_callback_query(&query.id).await?;
+        return Ok(());
+    };
+    let session_chat = state
+        .database
+        .radio_session_chat(session_id)
+        .await
+        .ok()
+        .flatten();
+    if session_chat != Some(msg.chat.id.0) {
+        bot.answer_callback_query(&query.id)
+            .text("电台已停止")
+            .await?;
+        state
+            .queue
+            .progress(
+                msg.chat.id,
+                bot.edit_message_reply_markup(msg.chat.id, msg.id),
+            )
+            .await
+            .ok();
+        return Ok(());
+    }
+
+    // Ignore presses while the previous one is still looking up or sending a song
+    if !state.radio_busy.lock().unwrap().insert(session_id) {
+        bot.answer_callback_query(&query.id)
+            .text("正在获取下一首...")
+            .await?;
+        return Ok(());
+    }
+    let result = play_next_radio_track(bot, query, msg, state, session_id).await;
+    state.radio_busy.lock().unwrap().remove(&session_id);
+    result
+}
+
+async fn play_next_radio_track(
+    bot: &Bot,
+    query: &CallbackQuery,
+    msg: &Message,
+    state: &Arc<BotState>,
+    session_id: i64,
+) -> ResponseResult<()> {
+    let played = state
+        .database
+        .radio_tracks(session_id)
+        .await
+        .unwrap_or_default();
+    let music_id = match next_radio_track(state, &played).await {
+        Ok(Some(music_id)) => music_id,
+        Ok(None) => {
+            bot.answer_callback_query(&query.id)
+                .text("没有更多相似歌曲了")
+                .show_alert(true)
+                .await?;
+            return Ok(());
+        }
+        Err(e) => {
+            bot.answer_callback_query(&query.id)
+                .text(format!("❌ 获取相似歌曲失败: {e}"))
+                .await?;
+            return Ok(());
+        }
+    };
+    bot.answer_callback_query(&query.id)
+        .text("⏭ 下一首")
+        .await?;
+
+    let delivered = deliver_music(bot, msg, state, Some(&query.from), music_id, None).await;
+    // Only delivered songs count as played
+    let mut played = played.len();
+    if matches!(delivered, Ok(true)) {
+        if let Err(e) = state
+            .database
+            .add_radio_track(session_id, music_id as i64)
+            .await
+        {
+            tracing::warn!("Failed to record radio track: {}", e);
+        }
+        played += 1;
+    }
+
+    // Only the newest controls keep their buttons, and they always follow the last message
+    state
+        .queue
+        .progress(
+            msg.chat.id,
+            bot.edit_message_reply_markup(msg.chat.id, msg.id),
+        )
+        .await
+        .ok();
+    send_radio_controls(bot, msg, state, session_id, played).await?;
+    delivered.map(|_| ())
+}
+
+/// Stop a radio session and remove its buttons
+async fn handle_radio_stop_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    state: &Arc<BotState>,
+    session_id: i64,
+) -> ResponseResult<()> {
+    let Some(msg) = query.message.as_ref() else {
+        bot.answer_callback_query(&query.id).await?;
+        return Ok(());
+    };
+    if state
+        .database
+        .radio_session_chat(session_id)
+        .await
+        .ok()
+        .flatten()
+        == Some(msg.chat.id.0)
+    {
+        if let Err(e) = state.database.delete_radio_session(session_id).await {
+            tracing::warn!("Failed to delete radio session {}: {}", session_id, e);
+        }
+    }
+
+    bot.answer_callback_query(&query.id)
+        .text("⏹ 电台已停止")
+        .await?;
+    state
+        .queue
+        .progress(
+            msg.chat.id,
+            bot.edit_message_text(msg.chat.id, msg.id, "📻 电台已停止"),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Entries per chart or new album page
+const TOP_PAGE_SIZE: u32 = 10;
+/// Charts offered in the `/top` keyboard
+const TOPLIST_BUTTONS: usize = 16;
+
+/// A screen of the `/top` browser
+#[derive(Debug, Clone, Copy)]
+enum TopView {
+    Toplists,
+    /// Chart id and page
+    Chart(u64, u32),
+    /// Page of new albums
+    NewAlbums(u32),
+}
+
+async fn load_toplists(state: &Arc<BotState>) -> Result<Arc<Vec<Toplist>>> {
+    if let Some(toplists) = state.toplists.get(&()) {
+        return Ok(toplists);
+    }
+    let toplists = Arc::new(state.music_api.get_toplists().await?);
+    state.toplists.insert((), Arc::clone(&toplists));
+    Ok(toplists)
+}
+
+async fn load_chart(state: &Arc<BotState>, toplist_id: u64) -> Result<Arc<Playlist>> {
+    if let Some(chart) = state.charts.get(&toplist_id) {
+        return Ok(chart);
+    }
+    let chart = Arc::new(state.music_api.get_toplist(toplist_id).await?);
+    state.charts.insert(toplist_id, Arc::clone(&chart));
+    Ok(chart)
+}
+
+/// Previous/next buttons for a paged `/top` screen
+fn top_navigation(
+    page: u32,
+    total_pages: u64,
+    callback: impl Fn(u32) -> String,
+) -> Vec<InlineKeyboardButton> {
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(
+            "⬅️ 上一页",
+            callback(page - 1),
+        ));
+    }
+    if u64::from(page) + 1 < total_pages {
+        navigation.push(InlineKeyboardButton::callback(
+            "下一页 ➡️",
+            callback(page + 1),
+        ));
+    }
+    navigation
+}
+
+/// Text and keyboard of a `/top` screen
+async fn render_top_view(
+    state: &Arc<BotState>,
+    view: TopView,
+) -> Result<(String, InlineKeyboardMarkup)> {
+    let back = vec![InlineKeyboardButton::callback(
+        "🔙 返回榜单列表",
+        "toplists",
+    )];
+    match view {
+        TopView::Toplists => {
+            let toplists = load_toplists(state).await?;
+            let buttons: Vec<InlineKeyboardButton> = toplists
+                .iter()
+                .take(TOPLIST_BUTTONS)
+                .map(|toplist| {
+                    InlineKeyboardButton::callback(&toplist.name, format!("top {} 0", toplist.id))
+                })
+                .collect();
+            let mut rows: Vec<Vec<InlineKeyboardButton>> = buttons
+                .chunks(2)
+                .map(<[InlineKeyboardButton]>::to_vec)
+                .collect();
+            rows.push(vec![InlineKeyboardButton::callback(
+                "💿 新碟上架",
+                "newalbums 0",
+            )]);
+            Ok((
+                "📊 网易云音乐排行榜\n\n请选择要查看的榜单:".to_string(),
+                InlineKeyboardMarkup::new(rows),
+            ))
+        }
+        TopView::Chart(toplist_id, page) => {
+            let chart = load_chart(state, toplist_id).await?;
+            let total_pages = (chart.tracks.len() as u64)
+                .div_ceil(u64::from(TOP_PAGE_SIZE))
+                .max(1);
+            let offset = page * TOP_PAGE_SIZE;
+            let update = load_toplists(state)
+                .await
+                .ok()
+                .and_then(|toplists| {
+                    toplists
+                        .iter()
+                        .find(|toplist| toplist.id == toplist_id)
+                        .and_then(|toplist| toplist.update_frequency.clone())
+                })
+                .map(|frequency| format!(" · {frequency}"))
+                .unwrap_or_default();
+
+            let mut text = format!(
+                "📊 {}{update} · 第 {}/{} 页\n\n",
+                chart.name,
+                page + 1,
+                total_pages
+            );
+            let mut buttons = Vec::new();
+            for (i, track) in (offset + 1..).zip(
+                chart
+                    .tracks
+                    .iter()
+                    .skip(offset as usize)
+                    .take(TOP_PAGE_SIZE as usize),
+            ) {
+                let artists = track.ar.as_deref().map(format_artists).unwrap_or_default();
+                let duration = track
+                    .dt
+                    .map(|dt| format!(" · {}", format_duration(dt / 1000)))
+                    .unwrap_or_default();
+                text.push_str(&format!("{i}.「{}」 - {artists}{duration}\n", track.name));
+                buttons.push(InlineKeyboardButton::callback(
+                    i.to_string(),
+                    format!("music {}", track.id),
+                ));
             }
-            Err(e) => {
-                bot.send_message(msg.chat.id, format!("搜索失败: {e}"))
-                    .reply_to_message_id(msg.id)
-                    .await?;
-                return Ok(());
+
+            let mut rows: Vec<Vec<InlineKeyboardButton>> = buttons
+                .chunks(5)
+                .map(<[InlineKeyboardButton]>::to_vec)
+                .collect();
+            let navigation =
+                top_navigation(page, total_pages, |page| format!("top {toplist_id} {page}"));
+            if !navigation.is_empty() {
+                rows.push(navigation);
+            }
+            rows.push(back);
+            Ok((text, InlineKeyboardMarkup::new(rows)))
+        }
+        TopView::NewAlbums(page) => {
+            let data = state
+                .music_api
+                .get_new_albums(TOP_PAGE_SIZE, page * TOP_PAGE_SIZE)
+                .await?;
+            let total_pages = data.total.div_ceil(u64::from(TOP_PAGE_SIZE)).max(1);
+            let offset = page * TOP_PAGE_SIZE;
+
+            let mut text = format!("💿 新碟上架 · 第 {}/{} 页\n\n", page + 1, total_pages);
+            let mut buttons = Vec::new();
+            for (i, album) in (offset + 1..).zip(&data.albums) {
+                text.push_str(&format!(
+                    "{i}.「{}」 - {}\n",
+                    album.name,
+                    format_artists(&album.artists)
+                ));
+                buttons.push(InlineKeyboardButton::callback(
+                    i.to_string(),
+                    format!("album {}", album.id),
+                ));
             }
+
+            let mut rows: Vec<Vec<InlineKeyboardButton>> = buttons
+                .chunks(5)
+                .map(<[InlineKeyboardButton]>::to_vec)
+                .collect();
+            let navigation = top_navigation(page, total_pages, |page| format!("newalbums {page}"));
+            if !navigation.is_empty() {
+                rows.push(navigation);
+            }
+            rows.push(back);
+            Ok((text, InlineKeyboardMarkup::new(rows)))
+        }
+    }
+}
+
+/// `/top [榜单名]`: pick a chart, or open the chart whose name matches
+async fn handle_top_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let name = args.unwrap_or_default();
+    let view = if name.is_empty() {
+        TopView::Toplists
+    } else {
+        let found = load_toplists(state).await.ok().and_then(|toplists| {
+            toplists
+                .iter()
+                .find(|toplist| toplist.name.contains(name.trim()))
+                .map(|toplist| toplist.id)
+        });
+        found.map_or(TopView::Toplists, |id| TopView::Chart(id, 0))
+    };
+
+    let request = match render_top_view(state, view).await {
+        Ok((text, keyboard)) => bot.send_message(msg.chat.id, text).reply_markup(keyboard),
+        Err(e) => bot.send_message(msg.chat.id, format!("获取排行榜失败: {e}")),
+    };
+    state
+        .queue
+        .deliver(msg.chat.id, request.reply_to_message_id(msg.id))
+        .await?;
+    Ok(())
+}
+
+/// Switch a `/top` message to another screen
+async fn handle_top_callback(
+    bot: &Bot,
+    query: &CallbackQuery,
+    state: &Arc<BotState>,
+    view: TopView,
+) -> ResponseResult<()> {
+    let Some(msg) = query.message.as_ref() else {
+        bot.answer_callback_query(&query.id).await?;
+        return Ok(());
+    };
+
+    match render_top_view(state, view).await {
+        Ok((text, keyboard)) => {
+            bot.answer_callback_query(&query.id).await?;
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.edit_message_text(msg.chat.id, msg.id, text)
+                        .reply_markup(keyboard),
+                )
+                .await?;
+        }
+        Err(e) => {
+            bot.answer_callback_query(&query.id)
+                .text(format!("❌ 获取排行榜失败: {e}"))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// List the album a song belongs to
+async fn show_song_album(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    music_id: u64,
+) -> ResponseResult<()> {
+    match state.music_api.get_song_detail(music_id).await {
+        Ok(SongDetail {
+            al: Some(album), ..
+        }) if album.id > 0 => show_collection(bot, msg, state, NeteaseLink::Album(album.id)).await,
+        Ok(_) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, "该歌曲没有专辑信息")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            Ok(())
+        }
+        Err(e) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("获取歌曲信息失败: {e}"))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+async fn handle_about_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+) -> ResponseResult<()> {
+    let about_text = format!(
+        r"🎵 Music163bot-Rust v{}
+
+一个用来下载/分享/搜索网易云歌曲的 Telegram Bot
+
+特性：
+• 🔗 分享链接嗅探
+• 🎵 歌曲搜索与下载
+• 💾 智能缓存系统
+• 🚀 智能存储 (v1.1.0+)
+• 🎤 歌词获取
+• 📊 使用统计
+
+技术栈：
+• 🦀 Rust + Teloxide
+• 🔧 高并发处理
+• 📦 轻量级部署
+
+源码：GitHub | 原版：Music163bot-Go",
+        env!("CARGO_PKG_VERSION")
+    );
+
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, about_text)
+                .reply_to_message_id(msg.id)
+                .disable_web_page_preview(true),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn handle_lyric_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let args = args.unwrap_or_default();
+
+    if args.is_empty() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "请输入歌曲ID或关键词")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let music_id = if let Some(id) = parse_music_id(&args) {
+        id
+    } else {
+        match resolve_song_query(bot, msg, state, &args, "lyric").await? {
+            Some(music_id) => music_id,
+            None => return Ok(()),
         }
     };
 
-    let status_msg = bot
-        .send_message(msg.chat.id, "🎵 正在获取歌词...")
-        .reply_to_message_id(msg.id)
+    let status_msg = state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, "🎵 正在获取歌词...")
+                .reply_to_message_id(msg.id),
+        )
         .await?;
 
     match state.music_api.get_song_lyric(music_id).await {
         Ok(lyric) => {
             if lyric.trim().is_empty() || lyric == "No lyrics available" {
-                bot.edit_message_text(msg.chat.id, status_msg.id, "该歌曲暂无歌词")
+                state
+                    .queue
+                    .progress(
+                        msg.chat.id,
+                        bot.edit_message_text(msg.chat.id, status_msg.id, "该歌曲暂无歌词"),
+                    )
                     .await?;
                 return Ok(());
             }
@@ -1164,12 +3033,17 @@ async fn handle_lyric_command(
             let song_detail = match state.music_api.get_song_detail(music_id).await {
                 Ok(detail) => detail,
                 Err(e) => {
-                    bot.edit_message_text(
-                        msg.chat.id,
-                        status_msg.id,
-                        format!("获取歌曲信息失败: {e}"),
-                    )
-                    .await?;
+                    state
+                        .queue
+                        .progress(
+                            msg.chat.id,
+                            bot.edit_message_text(
+                                msg.chat.id,
+                                status_msg.id,
+                                format!("获取歌曲信息失败: {e}"),
+                            ),
+                        )
+                        .await?;
                     return Ok(());
                 }
             };
@@ -1180,25 +3054,236 @@ async fn handle_lyric_command(
 
             tokio::fs::write(&lrc_path, &lyric).await?;
 
-            bot.send_document(
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_document(
+                        msg.chat.id,
+                        InputFile::file(std::path::Path::new(&lrc_path)),
+                    )
+                    .reply_to_message_id(msg.id),
+                )
+                .await?;
+
+            tokio::fs::remove_file(&lrc_path).await.ok();
+            state
+                .queue
+                .progress(msg.chat.id, bot.delete_message(msg.chat.id, status_msg.id))
+                .await
+                .ok();
+        }
+        Err(e) => {
+            state
+                .queue
+                .progress(
+                    msg.chat.id,
+                    bot.edit_message_text(msg.chat.id, status_msg.id, format!("获取歌词失败: {e}")),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// `/info <id|link|关键词>`: show a song card without downloading it
+async fn handle_info_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let args = args.unwrap_or_default();
+
+    if args.is_empty() {
+        state
+            .queue
+            .deliver(
                 msg.chat.id,
-                InputFile::file(std::path::Path::new(&lrc_path)),
+                bot.send_message(msg.chat.id, "请输入歌曲ID、链接或关键词")
+                    .reply_to_message_id(msg.id),
             )
-            .reply_to_message_id(msg.id)
             .await?;
+        return Ok(());
+    }
 
-            tokio::fs::remove_file(&lrc_path).await.ok();
-            bot.delete_message(msg.chat.id, status_msg.id).await.ok();
+    let music_id = if let Some(id) = parse_music_id(&args) {
+        id
+    } else {
+        match resolve_song_query(bot, msg, state, &args, "info").await? {
+            Some(music_id) => music_id,
+            None => return Ok(()),
         }
+    };
+
+    show_song_info(bot, msg, state, music_id).await
+}
+
+/// Reply with the cover, metadata, available qualities and cache status of a song,
+/// with a download button per quality
+async fn show_song_info(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    music_id: u64,
+) -> ResponseResult<()> {
+    let (song, privilege) = match state.music_api.get_song_info(music_id).await {
+        Ok(info) => info,
         Err(e) => {
-            bot.edit_message_text(msg.chat.id, status_msg.id, format!("获取歌词失败: {e}"))
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("获取歌曲信息失败: {e}"))
+                        .reply_to_message_id(msg.id),
+                )
                 .await?;
+            return Ok(());
+        }
+    };
+    let cached = cached_song(state, music_id).await;
+    let (text, keyboard) = render_song_info(
+        &song,
+        privilege.as_ref(),
+        cached.as_ref(),
+        state.endpoints.upload_limit(),
+    );
+
+    let cover = song
+        .al
+        .as_ref()
+        .and_then(|al| al.pic_url.as_deref())
+        .and_then(|url| reqwest::Url::parse(url).ok());
+    if let Some(cover) = cover {
+        let sent = state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_photo(msg.chat.id, InputFile::url(cover))
+                    .caption(text.clone())
+                    .reply_markup(keyboard.clone())
+                    .reply_to_message_id(msg.id),
+            )
+            .await;
+        match sent {
+            Ok(_) => return Ok(()),
+            Err(e) => tracing::warn!("Failed to send cover for music_id {}: {}", music_id, e),
         }
     }
 
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(keyboard)
+                .reply_to_message_id(msg.id),
+        )
+        .await?;
     Ok(())
 }
 
+/// Text and keyboard of a song info card
+fn render_song_info(
+    song: &SongDetail,
+    privilege: Option<&Privilege>,
+    cached: Option<&SongInfo>,
+    upload_limit: u64,
+) -> (String, InlineKeyboardMarkup) {
+    let mut text = format!("🎵 {}", song.name);
+    let mut other_names: Vec<&str> = song.alia.iter().map(String::as_str).collect();
+    other_names.extend(song.tns.iter().flatten().map(String::as_str));
+    if !other_names.is_empty() {
+        text.push_str(&format!(" ({})", other_names.join(" / ")));
+    }
+    text.push('\n');
+
+    text.push_str(&format!(
+        "👤 {}\n",
+        format_artists(song.ar.as_deref().unwrap_or(&[]))
+    ));
+    if let Some(album) = &song.al {
+        text.push_str(&format!("💿 {}\n", album.name));
+    }
+    if let Some(date) = song
+        .publish_time
+        .filter(|&ts| ts > 0)
+        .and_then(chrono::DateTime::from_timestamp_millis)
+    {
+        text.push_str(&format!("📅 发行日期: {}\n", date.format("%Y-%m-%d")));
+    }
+    if let Some(dt) = song.dt {
+        text.push_str(&format!("⏱ 时长: {}\n", format_duration(dt / 1000)));
+    }
+    if let Some(pop) = song.pop {
+        text.push_str(&format!("🔥 热度: {pop:.0}\n"));
+    }
+
+    let fee = privilege.map(|p| p.fee).or(song.fee).unwrap_or(0);
+    let fee_text = match fee {
+        1 => "VIP 歌曲",
+        4 => "付费专辑",
+        _ => "免费",
+    };
+    text.push_str(&format!("💰 {fee_text}\n"));
+    if privilege.is_some_and(|p| p.st < 0) {
+        text.push_str("⚠️ 该歌曲已下架\n");
+    }
+
+    let qualities = song.qualities();
+    if !qualities.is_empty() {
+        text.push_str("\n🎧 可用音质:\n");
+        for &(level, size) in &qualities {
+            let note = if size > upload_limit {
+                " (超过上传限制)"
+            } else {
+                ""
+            };
+            text.push_str(&format!(
+                "  • {} · {}{note}\n",
+                quality_label(level),
+                format_file_size(size)
+            ));
+        }
+    }
+    if let Some(pl) = privilege.map(|p| p.pl).filter(|&pl| pl > 0) {
+        text.push_str(&format!("🔓 当前账号最高可播放: {}\n", quality_label(pl)));
+    }
+
+    match cached {
+        Some(cached) => text.push_str(&format!(
+            "\n💾 已缓存: {} · {}",
+            quality_label(cached_level(cached)),
+            format_file_size(cached.music_size as u64)
+        )),
+        None => text.push_str("\n💾 未缓存"),
+    }
+
+    // Levels beyond what the account may play would silently fall back to a lower one
+    let playable = privilege.map_or(0, |p| p.pl);
+    let buttons: Vec<InlineKeyboardButton> = qualities
+        .iter()
+        .filter(|&&(level, size)| size <= upload_limit && (playable == 0 || level <= playable))
+        .map(|&(level, _)| {
+            InlineKeyboardButton::callback(
+                format!("⬇️ {}", quality_label(level)),
+                format!("music {} {level}", song.id),
+            )
+        })
+        .collect();
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = buttons
+        .chunks(2)
+        .map(<[InlineKeyboardButton]>::to_vec)
+        .collect();
+    rows.push(vec![InlineKeyboardButton::url(
+        "🔗 在网易云音乐中打开",
+        reqwest::Url::parse(&NeteaseLink::Song(song.id).url()).unwrap(),
+    )]);
+
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
 async fn handle_status_command(
     bot: &Bot,
     msg: &Message,
@@ -1219,6 +3304,18 @@ async fn handle_status_command(
         .await
         .unwrap_or(0);
 
+    let endpoint_status = markdown::escape(&state.endpoints.status_text());
+    let accounts = state.music_api.accounts().accounts();
+    let account_status = if accounts.is_empty() {
+        "未登录".to_string()
+    } else {
+        let summaries: Vec<String> = accounts
+            .iter()
+            .map(|account| format!("\n  {}", markdown::escape(&account.summary())))
+            .collect();
+        summaries.concat()
+    };
+
     let status_text = format!(
         r"📊 *统计信息*
 
@@ -1227,115 +3324,700 @@ async fn handle_status_command(
 💬 当前对话缓存歌曲数量: {chat_count}
 
 🤖 Bot 运行状态: 正常
+📡 Telegram API: {endpoint_status}
+☁️ 网易云账号: {account_status}
 🦀 语言: Rust
 ⚡ 框架: Teloxide
 "
     );
 
-    bot.send_message(msg.chat.id, status_text)
-        .parse_mode(ParseMode::MarkdownV2)
-        .reply_to_message_id(msg.id)
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, status_text)
+                .parse_mode(ParseMode::MarkdownV2)
+                .reply_to_message_id(msg.id),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// `/autosearch [on|off]`: toggle plain text and mention search for the current chat
+async fn handle_autosearch_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    let chat_id = msg.chat.id;
+    let enabled = match args.as_deref().map(str::trim) {
+        None | Some("") => {
+            let current = state
+                .database
+                .is_auto_search_enabled(chat_id.0)
+                .await
+                .unwrap_or(true);
+            let status = if current { "开启" } else { "关闭" };
+            state
+                .queue
+                .deliver(
+                    chat_id,
+                    bot.send_message(
+                        chat_id,
+                        format!(
+                            "当前对话的自动搜索已{status}\n\n用法: /autosearch on 或 /autosearch off"
+                        ),
+                    )
+                    .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(());
+        }
+        Some("on") => true,
+        Some("off") => false,
+        Some(_) => {
+            state
+                .queue
+                .deliver(
+                    chat_id,
+                    bot.send_message(chat_id, "用法: /autosearch on 或 /autosearch off")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // In groups only chat administrators and bot admins may change the setting
+    if !msg.chat.is_private() {
+        let Some(user) = msg.from() else {
+            return Ok(());
+        };
+        let is_bot_admin = state.config.bot_admin.contains(&(user.id.0 as i64));
+        let is_chat_admin = bot
+            .get_chat_member(chat_id, user.id)
+            .await
+            .is_ok_and(|member| member.is_privileged());
+        if !is_bot_admin && !is_chat_admin {
+            state
+                .queue
+                .deliver(
+                    chat_id,
+                    bot.send_message(chat_id, "❌ 只有群组管理员可以修改此设置")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(());
+        }
+    }
+
+    let reply = match state.database.set_auto_search(chat_id.0, enabled).await {
+        Ok(()) if enabled => "✅ 已开启自动搜索".to_string(),
+        Ok(()) => "✅ 已关闭自动搜索，仍可使用 /search 搜索".to_string(),
+        Err(e) => format!("保存设置失败: {e}"),
+    };
+    state
+        .queue
+        .deliver(
+            chat_id,
+            bot.send_message(chat_id, reply).reply_to_message_id(msg.id),
+        )
         .await?;
+    Ok(())
+}
+
+async fn handle_rmcache_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+    args: Option<String>,
+) -> ResponseResult<()> {
+    // Check if user is admin
+    let user_id = msg.from().map_or(0, |u| u.id.0 as i64);
+
+    tracing::info!(
+        "rmcache command from user_id: {}, configured admins: {:?}",
+        user_id,
+        state.config.bot_admin
+    );
+
+    if !state.config.bot_admin.contains(&user_id) {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "❌ 该命令仅限管理员使用")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let args = args.unwrap_or_default();
+
+    if args.is_empty() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(
+                    msg.chat.id,
+                    "请输入要删除缓存的歌曲ID\n\n用法: `/rmcache <音乐ID>`",
+                )
+                .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(music_id) = parse_music_id(&args) {
+        let music_id_i64 = music_id as i64;
+
+        // Get song info before deletion
+        if let Ok(Some(song_info)) = state.database.get_song_by_music_id(music_id_i64).await {
+            match state.database.delete_song_by_music_id(music_id_i64).await {
+                Ok(deleted) => {
+                    if deleted {
+                        state
+                            .queue
+                            .deliver(
+                                msg.chat.id,
+                                bot.send_message(
+                                    msg.chat.id,
+                                    format!("✅ 已删除歌曲缓存: {}", song_info.song_name),
+                                )
+                                .reply_to_message_id(msg.id),
+                            )
+                            .await?;
+                    } else {
+                        state
+                            .queue
+                            .deliver(
+                                msg.chat.id,
+                                bot.send_message(msg.chat.id, "歌曲未缓存")
+                                    .reply_to_message_id(msg.id),
+                            )
+                            .await?;
+                    }
+                }
+                Err(e) => {
+                    state
+                        .queue
+                        .deliver(
+                            msg.chat.id,
+                            bot.send_message(msg.chat.id, format!("删除缓存失败: {e}"))
+                                .reply_to_message_id(msg.id),
+                        )
+                        .await?;
+                }
+            }
+        } else {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, "歌曲未缓存")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+        }
+    } else {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "无效的歌曲ID")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Interval between QR code login status checks
+const QR_LOGIN_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Status checks before a QR code login is given up
+const QR_LOGIN_POLLS: u32 = 100;
+
+/// `/login`: log in to NetEase by scanning a QR code sent to the admin privately
+async fn handle_login_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
+) -> ResponseResult<()> {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    if !state.config.bot_admin.contains(&(user.id.0 as i64)) {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "❌ 该命令仅限管理员使用")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    if state.login_pending.swap(true, Ordering::SeqCst) {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "已有一个登录二维码等待扫描")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let Some((private_chat, key, qr_msg)) = send_login_qr(bot, msg, state, user).await? else {
+        state.login_pending.store(false, Ordering::SeqCst);
+        return Ok(());
+    };
 
+    let state = Arc::clone(state);
+    let bot = bot.clone();
+    tokio::spawn(async move {
+        let text = match poll_qr_login(&state, &key, "/login").await {
+            Ok((music_u, nickname)) => {
+                add_pool_account(&state, &music_u, nickname.as_deref()).await
+            }
+            Err(text) => text,
+        };
+        state.login_pending.store(false, Ordering::SeqCst);
+        state
+            .queue
+            .progress(
+                private_chat,
+                bot.edit_message_caption(private_chat, qr_msg.id)
+                    .caption(text),
+            )
+            .await
+            .ok();
+    });
     Ok(())
 }
 
-async fn handle_rmcache_command(
+/// Store an account an admin logged in with and add it to the pool.
+/// Returns the text to show under the QR code.
+async fn add_pool_account(state: &BotState, music_u: &str, nickname: Option<&str>) -> String {
+    let account = state.music_api.accounts().add(music_u);
+    tracing::info!("NetEase account #{} added after QR login", account.id());
+    let health = account::check_account(&state.music_api, &state.database, &account).await;
+    // Logging in again replaces the stored cookie of the same NetEase user
+    let user_id = match &health {
+        AccountHealth::Active { user_id, .. } => Some(*user_id as i64),
+        _ => None,
+    };
+    account.set_health(health);
+    if let Err(e) = state
+        .database
+        .save_account(&account.music_u(), user_id, nickname)
+        .await
+    {
+        tracing::error!("Failed to store NetEase account: {}", e);
+    }
+    "✅ 登录成功，账号已加入账号池".to_string()
+}
+
+/// Create a login QR code and send it to `user`. The QR code grants access to the
+/// account, so it only goes to a private chat.
+/// Returns the private chat, the QR key and the sent photo, or `None` after telling
+/// the user what went wrong.
+async fn send_login_qr(
+    bot: &Bot,
+    msg: &Message,
+    state: &BotState,
+    user: &User,
+) -> ResponseResult<Option<(ChatId, String, Message)>> {
+    let qr_code = match state.music_api.create_qr_login_key().await {
+        Ok(key) => qr_code_png(&qr_login_url(&key)).map(|png| (key, png)),
+        Err(e) => Err(e),
+    };
+    let (key, png) = match qr_code {
+        Ok(qr_code) => qr_code,
+        Err(e) => {
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, format!("创建登录二维码失败: {e}"))
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(None);
+        }
+    };
+
+    let private_chat = ChatId(user.id.0 as i64);
+    let sent = state
+        .queue
+        .deliver(
+            private_chat,
+            bot.send_photo(private_chat, InputFile::memory(png).file_name("login.png"))
+                .caption("请使用网易云音乐 App 扫描二维码并确认登录\n二维码将在几分钟后过期"),
+        )
+        .await;
+    let qr_msg = match sent {
+        Ok(qr_msg) => qr_msg,
+        Err(e) => {
+            tracing::warn!("Failed to send login QR code: {}", e);
+            state
+                .queue
+                .deliver(
+                    msg.chat.id,
+                    bot.send_message(msg.chat.id, "无法私聊发送二维码，请先私聊机器人发送 /start")
+                        .reply_to_message_id(msg.id),
+                )
+                .await?;
+            return Ok(None);
+        }
+    };
+    if !msg.chat.is_private() {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "登录二维码已通过私聊发送")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
+    }
+
+    Ok(Some((private_chat, key, qr_msg)))
+}
+
+/// Wait for a QR code login to finish. Returns the cookie and nickname of the account,
+/// or the failure to show under the QR code, suggesting `command` to try again.
+async fn poll_qr_login(
+    state: &BotState,
+    key: &str,
+    command: &str,
+) -> std::result::Result<(String, Option<String>), String> {
+    let mut scanned = false;
+    let mut scanned_by: Option<String> = None;
+    for _ in 0..QR_LOGIN_POLLS {
+        tokio::time::sleep(QR_LOGIN_POLL_INTERVAL).await;
+        match state.music_api.check_qr_login(key).await {
+            Ok(QrLoginStatus::Waiting) => {}
+            Ok(QrLoginStatus::Scanned { nickname }) => {
+                if !scanned {
+                    scanned = true;
+                    tracing::info!(
+                        "Login QR code scanned by {}",
+                        nickname.as_deref().unwrap_or("unknown user")
+                    );
+                }
+                scanned_by = nickname.or(scanned_by);
+            }
+            Ok(QrLoginStatus::Expired) => {
+                return Err(format!("❌ 二维码已过期，请重新发送 {command}"));
+            }
+            Ok(QrLoginStatus::Confirmed { music_u }) => return Ok((music_u, scanned_by)),
+            Err(e) => tracing::warn!("Failed to check QR login: {}", e),
+        }
+    }
+    Err(format!("❌ 登录超时，请重新发送 {command}"))
+}
+
+/// `/bind [cookie]`: link the user's own NetEase account, used for the songs they request.
+/// Without a cookie a login QR code is sent privately.
+async fn handle_bind_command(
     bot: &Bot,
     msg: &Message,
     state: &Arc<BotState>,
     args: Option<String>,
 ) -> ResponseResult<()> {
-    // Check if user is admin
-    let user_id = msg.from().map_or(0, |u| u.id.0 as i64);
-
-    tracing::info!(
-        "rmcache command from user_id: {}, configured admins: {:?}",
-        user_id,
-        state.config.bot_admin
-    );
-
-    if !state.config.bot_admin.contains(&user_id) {
-        bot.send_message(msg.chat.id, "❌ 该命令仅限管理员使用")
-            .reply_to_message_id(msg.id)
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let user_id = user.id.0 as i64;
+
+    if let Some(cookie) = args {
+        // The cookie grants access to the account, so it must not stay in the chat
+        let deleted = state
+            .queue
+            .deliver(msg.chat.id, bot.delete_message(msg.chat.id, msg.id))
+            .await
+            .is_ok();
+        let text = if !msg.chat.is_private() {
+            let mut text = "⚠️ 请不要在群组中发送 Cookie，请私聊机器人使用 /bind".to_string();
+            if !deleted {
+                text.push_str("\n请立即删除包含 Cookie 的消息");
+            }
+            text
+        } else if let Some(music_u) = parse_music_u(&cookie) {
+            match state.music_api.get_account_profile(&music_u).await {
+                Ok(Some(profile)) => {
+                    bind_user_account(state, user_id, &music_u, Some(&profile.nickname)).await
+                }
+                Ok(None) => "❌ Cookie 无效或已过期".to_string(),
+                Err(e) => format!("❌ 验证 Cookie 失败: {e}"),
+            }
+        } else {
+            "❌ 无法识别 Cookie，请发送 MUSIC_U 的值".to_string()
+        };
+        state
+            .queue
+            .deliver(msg.chat.id, bot.send_message(msg.chat.id, text))
             .await?;
         return Ok(());
     }
 
-    let args = args.unwrap_or_default();
-
-    if args.is_empty() {
-        bot.send_message(
-            msg.chat.id,
-            "请输入要删除缓存的歌曲ID\n\n用法: `/rmcache <音乐ID>`",
-        )
-        .reply_to_message_id(msg.id)
-        .await?;
+    if !state.bind_pending.lock().unwrap().insert(user_id) {
+        state
+            .queue
+            .deliver(
+                msg.chat.id,
+                bot.send_message(msg.chat.id, "已有一个登录二维码等待扫描")
+                    .reply_to_message_id(msg.id),
+            )
+            .await?;
         return Ok(());
     }
 
-    if let Some(music_id) = parse_music_id(&args) {
-        let music_id_i64 = music_id as i64;
+    let Some((private_chat, key, qr_msg)) = send_login_qr(bot, msg, state, user).await? else {
+        state.bind_pending.lock().unwrap().remove(&user_id);
+        return Ok(());
+    };
 
-        // Get song info before deletion
-        if let Ok(Some(song_info)) = state.database.get_song_by_music_id(music_id_i64).await {
-            match state.database.delete_song_by_music_id(music_id_i64).await {
-                Ok(deleted) => {
-                    if deleted {
-                        bot.send_message(
-                            msg.chat.id,
-                            format!("✅ 已删除歌曲缓存: {}", song_info.song_name),
-                        )
-                        .reply_to_message_id(msg.id)
-                        .await?;
-                    } else {
-                        bot.send_message(msg.chat.id, "歌曲未缓存")
-                            .reply_to_message_id(msg.id)
-                            .await?;
-                    }
-                }
-                Err(e) => {
-                    bot.send_message(msg.chat.id, format!("删除缓存失败: {e}"))
-                        .reply_to_message_id(msg.id)
-                        .await?;
-                }
+    let state = Arc::clone(state);
+    let bot = bot.clone();
+    tokio::spawn(async move {
+        let text = match poll_qr_login(&state, &key, "/bind").await {
+            Ok((music_u, nickname)) => {
+                bind_user_account(&state, user_id, &music_u, nickname.as_deref()).await
             }
-        } else {
-            bot.send_message(msg.chat.id, "歌曲未缓存")
-                .reply_to_message_id(msg.id)
-                .await?;
+            Err(text) => text,
+        };
+        state.bind_pending.lock().unwrap().remove(&user_id);
+        state
+            .queue
+            .progress(
+                private_chat,
+                bot.edit_message_caption(private_chat, qr_msg.id)
+                    .caption(text),
+            )
+            .await
+            .ok();
+    });
+    Ok(())
+}
+
+/// Encrypt and store the cookie a user bound. Returns the text to show the user.
+async fn bind_user_account(
+    state: &BotState,
+    user_id: i64,
+    music_u: &str,
+    nickname: Option<&str>,
+) -> String {
+    let stored = match state.credentials.encrypt(music_u) {
+        Ok(stored) => stored,
+        Err(e) => {
+            tracing::error!(
+                "Failed to encrypt NetEase account of user {}: {}",
+                user_id,
+                e
+            );
+            return "❌ 绑定失败，请稍后重试".to_string();
         }
-    } else {
-        bot.send_message(msg.chat.id, "无效的歌曲ID")
-            .reply_to_message_id(msg.id)
-            .await?;
+    };
+    if let Err(e) = state
+        .database
+        .bind_user_account(user_id, &stored, nickname)
+        .await
+    {
+        tracing::error!("Failed to store NetEase account of user {}: {}", user_id, e);
+        return "❌ 绑定失败，请稍后重试".to_string();
     }
 
-    Ok(())
+    tracing::info!("User {} bound a NetEase account", user_id);
+    format!(
+        "✅ 已绑定网易云账号{}\n之后你点播的歌曲将优先使用该账号获取，发送 /unbind 可解除绑定",
+        nickname
+            .map(|nickname| format!(" {nickname}"))
+            .unwrap_or_default()
+    )
 }
 
-async fn handle_callback(
-    bot: Bot,
-    query: CallbackQuery,
-    state: Arc<BotState>,
+/// Stop using a bound account NetEase logged out and ask its user to bind it again
+async fn forget_user_account(bot: &Bot, state: &BotState, user_id: i64) {
+    match state.database.mark_user_account_stale(user_id).await {
+        Ok(true) => {
+            tracing::info!("NetEase account bound by user {} is logged out", user_id);
+            // Binding happens in the private chat, so the bot may message the user there
+            let chat = ChatId(user_id);
+            state
+                .queue
+                .deliver(
+                    chat,
+                    bot.send_message(
+                        chat,
+                        "⚠️ 你绑定的网易云账号登录已失效，已改用机器人的账号获取歌曲\n请发送 /bind 重新绑定",
+                    ),
+                )
+                .await
+                .ok();
+        }
+        Ok(false) => {}
+        Err(e) => tracing::warn!(
+            "Failed to mark NetEase account of user {} as stale: {}",
+            user_id,
+            e
+        ),
+    }
+}
+
+/// `/unbind`: forget the user's own NetEase account
+async fn handle_unbind_command(
+    bot: &Bot,
+    msg: &Message,
+    state: &Arc<BotState>,
 ) -> ResponseResult<()> {
-    if let Some(data) = query.data {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let text = match state.database.unbind_user_account(user.id.0 as i64).await {
+        Ok(true) => {
+            tracing::info!("User {} unbound their NetEase account", user.id);
+            "✅ 已解除绑定，之后将使用机器人的账号获取歌曲".to_string()
+        }
+        Ok(false) => "你还没有绑定网易云账号".to_string(),
+        Err(e) => format!("❌ 解除绑定失败: {e}"),
+    };
+    state
+        .queue
+        .deliver(
+            msg.chat.id,
+            bot.send_message(msg.chat.id, text)
+                .reply_to_message_id(msg.id),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Music API client for songs requested by `user_id`: their bound account first,
+/// the account pool otherwise
+async fn user_music_api(state: &BotState, user_id: i64) -> MusicApi {
+    let stored = match state.database.user_account(user_id).await {
+        Ok(Some((stored, _))) => stored,
+        Ok(None) => return state.music_api.clone(),
+        Err(e) => {
+            tracing::warn!("Failed to load NetEase account of user {}: {}", user_id, e);
+            return state.music_api.clone();
+        }
+    };
+    match state.credentials.decrypt(&stored) {
+        Ok(music_u) => state.music_api.for_user(&music_u),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to decrypt NetEase account of user {}: {}",
+                user_id,
+                e
+            );
+            state.music_api.clone()
+        }
+    }
+}
+
+async fn handle_callback(query: CallbackQuery, state: Arc<BotState>) -> ResponseResult<()> {
+    let bot = state.endpoints.bot();
+    if let Some(data) = query.data.as_deref() {
         let parts: Vec<&str> = data.split_whitespace().collect();
+        if let ["search", token, page] = parts.as_slice() {
+            if let Ok(page) = page.parse::<u32>() {
+                return handle_search_page_callback(&bot, &query, &state, token, page).await;
+            }
+        }
+        if let [action @ ("radionext" | "radiostop"), id] = parts.as_slice() {
+            if let Ok(session_id) = id.parse::<i64>() {
+                return if *action == "radionext" {
+                    handle_radio_next_callback(&bot, &query, &state, session_id).await
+                } else {
+                    handle_radio_stop_callback(&bot, &query, &state, session_id).await
+                };
+            }
+        }
+        let top_view = match parts.as_slice() {
+            ["toplists"] => Some(TopView::Toplists),
+            ["top", id, page] => id
+                .parse()
+                .ok()
+                .zip(page.parse().ok())
+                .map(|(id, page)| TopView::Chart(id, page)),
+            ["newalbums", page] => page.parse().ok().map(TopView::NewAlbums),
+            _ => None,
+        };
+        if let Some(view) = top_view {
+            return handle_top_callback(&bot, &query, &state, view).await;
+        }
+        if let ["cpage", id, sort, page] = parts.as_slice() {
+            if let (Ok(music_id), Some(sort), Ok(page)) = (
+                id.parse::<u64>(),
+                CommentSort::from_code(sort),
+                page.parse::<u32>(),
+            ) {
+                return handle_comment_page_callback(&bot, &query, &state, music_id, sort, page)
+                    .await;
+            }
+        }
+        // Song actions reply to the message carrying the button
+        if let [action @ ("lyric" | "info" | "comments" | "similar" | "songalbum" | "radio"), id] =
+            parts.as_slice()
+        {
+            if let Ok(music_id) = id.parse::<u64>() {
+                let Some(msg) = query.message.as_ref() else {
+                    // Messages sent in inline mode have no chat to reply in
+                    bot.answer_callback_query(&query.id)
+                        .text(format!("请在与 @{} 的私聊中使用此功能", state.bot_username))
+                        .show_alert(true)
+                        .await?;
+                    return Ok(());
+                };
+                bot.answer_callback_query(&query.id).await?;
+                return match *action {
+                    "lyric" => {
+                        handle_lyric_command(&bot, msg, &state, Some(music_id.to_string())).await
+                    }
+                    "info" => show_song_info(&bot, msg, &state, music_id).await,
+                    "comments" => show_comments(&bot, msg, &state, music_id).await,
+                    "similar" => show_similar_songs(&bot, msg, &state, music_id).await,
+                    "radio" => start_radio_below(&bot, msg, &state, music_id).await,
+                    _ => show_song_album(&bot, msg, &state, music_id).await,
+                };
+            }
+        }
+        if let [kind @ ("album" | "artist" | "playlist"), id] = parts.as_slice() {
+            let link = id.parse::<u64>().ok().map(|id| match *kind {
+                "album" => NeteaseLink::Album(id),
+                "artist" => NeteaseLink::Artist(id),
+                _ => NeteaseLink::Playlist(id),
+            });
+            if let (Some(link), Some(msg)) = (link, query.message.as_ref()) {
+                bot.answer_callback_query(&query.id).await?;
+                return show_collection(&bot, msg, &state, link).await;
+            }
+        }
         if parts.len() >= 2 && parts[0] == "music" {
             if let Ok(music_id) = parts[1].parse::<u64>() {
-                let msg = query.message.as_ref().unwrap();
-                match process_music(&bot, msg, &state, music_id).await {
-                    Ok(()) => {
-                        bot.answer_callback_query(&query.id)
-                            .text("✅ 开始下载")
-                            .await?;
-                    }
-                    Err(e) => {
-                        tracing::error!("Error processing music from callback: {}", e);
-                        bot.answer_callback_query(&query.id)
-                            .text(format!("❌ 失败: {e}"))
-                            .await?;
-                    }
+                let quality = parts.get(2).and_then(|br| br.parse::<u64>().ok());
+                let Some(msg) = query.message.as_ref() else {
+                    bot.answer_callback_query(&query.id).await?;
+                    return Ok(());
+                };
+                // Downloads may outlast the window for answering the callback
+                bot.answer_callback_query(&query.id)
+                    .text("✅ 开始下载")
+                    .await?;
+                if let Err(e) =
+                    process_music(&bot, msg, &state, Some(&query.from), music_id, quality).await
+                {
+                    tracing::error!("Error processing music from callback: {}", e);
                 }
                 return Ok(());
             }
@@ -1349,23 +4031,263 @@ async fn handle_callback(
     Ok(())
 }
 
-async fn handle_inline_query(
-    bot: Bot,
-    query: InlineQuery,
-    state: Arc<BotState>,
-) -> ResponseResult<()> {
-    let text = query.query.trim();
+/// Caption for a song sent from its cached `file_id`
+fn cached_caption(song: &SongInfo, bot_username: &str) -> String {
+    // bitrate fallback if missing
+    let bitrate = if song.bit_rate > 0 {
+        song.bit_rate
+    } else {
+        let dur = (if song.duration > 0 { song.duration } else { 1 }) as f64;
+        (8.0 * song.music_size as f64 / dur) as i64
+    };
+    build_caption(
+        &song.song_name,
+        &song.song_artists,
+        &song.song_album,
+        &song.file_ext,
+        song.music_size,
+        bitrate,
+        bot_username,
+    )
+}
 
-    // Support "search" prefix for consistency with Go version
-    let (search_keyword, is_search_cmd) = if text.to_lowercase().starts_with("search ") {
-        let keyword = text[7..].trim();
-        (keyword, true)
-    } else if text.to_lowercase().starts_with("search") {
-        ("", true)
+/// The `QUALITY_LEVELS` entry a cached file was downloaded at
+fn cached_level(song: &SongInfo) -> u64 {
+    if song.file_ext == "flac" {
+        QUALITY_LEVELS[0]
     } else {
-        (text, false)
+        QUALITY_LEVELS
+            .iter()
+            .copied()
+            .find(|&level| song.bit_rate as u64 >= level)
+            .unwrap_or(QUALITY_LEVELS[QUALITY_LEVELS.len() - 1])
+    }
+}
+
+/// A cached song that can be re-sent by `file_id`
+async fn cached_song(state: &BotState, music_id: u64) -> Option<SongInfo> {
+    state
+        .database
+        .get_song_by_music_id(music_id as i64)
+        .await
+        .ok()
+        .flatten()
+        .filter(|song| song.file_id.is_some() && song.music_size > 1024)
+}
+
+/// Download and upload up to `INLINE_WARM_SONGS` of `music_ids` that are not cached yet to
+/// the cache chat in the background, one after another, so the next inline query can
+/// share them as cached audio
+fn warm_cache(state: &Arc<BotState>, music_ids: Vec<u64>, user: &User) {
+    let Some(cache_chat) = state.config.cache_chat else {
+        return;
+    };
+
+    let state = Arc::clone(state);
+    let target = SendTarget::cache_chat(ChatId(cache_chat), user);
+    tokio::spawn(async move {
+        let bot = state.endpoints.bot();
+        let mut warmed = 0;
+        for music_id in music_ids {
+            if warmed >= INLINE_WARM_SONGS {
+                break;
+            }
+            if cached_song(&state, music_id).await.is_some()
+                || !state.warming.lock().unwrap().insert(music_id)
+            {
+                continue;
+            }
+
+            tracing::info!("Warming cache for music_id {}", music_id);
+            warmed += 1;
+            match download_song(&bot, &state, music_id, None, &target).await {
+                Ok(true) => tracing::info!("Cached music_id {} via cache chat", music_id),
+                Ok(false) => tracing::warn!("Could not cache music_id {}", music_id),
+                Err(e) => tracing::warn!("Failed to cache music_id {}: {}", music_id, e),
+            }
+            state.warming.lock().unwrap().remove(&music_id);
+        }
+    });
+}
+
+/// Chosen inline songs go into the user's recent songs. The other songs of the same
+/// result page are likely picked next, so they are cached in the background.
+async fn handle_chosen_inline_result(
+    result: ChosenInlineResult,
+    state: Arc<BotState>,
+) -> ResponseResult<()> {
+    // Song results use "<music_id>_<index>" as result ID, recent songs "<music_id>_r<index>"
+    let Some((music_id, index)) = result
+        .result_id
+        .split_once('_')
+        .and_then(|(id, index)| Some((id.parse::<u64>().ok()?, index.parse::<u32>().ok())))
+    else {
+        return Ok(());
     };
 
+    let user_id = result.from.id.0 as i64;
+    if let Err(e) = state
+        .database
+        .record_history(user_id, user_id, music_id as i64)
+        .await
+    {
+        tracing::warn!("Failed to record song history: {}", e);
+    }
+
+    let (keyword, _) = strip_search_prefix(result.query.trim());
+    if let (Some(index), false) = (index, keyword.is_empty()) {
+        let (search_type, keyword) = SearchType::parse_query(keyword);
+        let offset = index / INLINE_PAGE_SIZE * INLINE_PAGE_SIZE;
+        if let Some(page) = state
+            .inline_pages
+            .get(&(search_type, keyword.to_string(), offset))
+        {
+            let others = page
+                .songs
+                .iter()
+                .map(|song| song.id)
+                .filter(|&id| id != music_id)
+                .collect();
+            warm_cache(&state, others, &result.from);
+        }
+    }
+    Ok(())
+}
+
+/// Inline results for a search: songs post `/netease <id>`, everything else posts its
+/// link, which the bot then expands into a track list
+fn inline_search_results(
+    search_type: SearchType,
+    result: &SearchResult,
+    offset: u32,
+    cached: &HashMap<u64, SongInfo>,
+    bot_username: &str,
+) -> Vec<InlineQueryResult> {
+    let article =
+        |id: String, title: &str, text: String, description: String, thumb: Option<&String>| {
+            let mut article = InlineQueryResultArticle::new(
+                id,
+                title,
+                InputMessageContent::Text(InputMessageContentText::new(text)),
+            )
+            .description(description);
+            article.thumb_url = thumb.and_then(|url| reqwest::Url::parse(url).ok());
+            InlineQueryResult::Article(article)
+        };
+
+    match search_type {
+        SearchType::Song | SearchType::Lyric => result
+            .songs
+            .iter()
+            .enumerate()
+            .map(|(i, song)| {
+                let id = format!("{}_{}", song.id, offset as usize + i);
+                // Cached songs are shared as audio, so the bot need not be in the chat
+                if let Some(info) = cached.get(&song.id) {
+                    if let Some(file_id) = &info.file_id {
+                        return InlineQueryResult::CachedAudio(
+                            InlineQueryResultCachedAudio::new(id, file_id)
+                                .caption(cached_caption(info, bot_username))
+                                .reply_markup(create_music_keyboard(
+                                    song.id,
+                                    &info.song_name,
+                                    &info.song_artists,
+                                )),
+                        );
+                    }
+                }
+                article(
+                    id,
+                    &song.name,
+                    format!("/netease {}", song.id),
+                    format_artists(&song.artists),
+                    song.album.pic_url.as_ref(),
+                )
+            })
+            .collect(),
+        SearchType::Album => result
+            .albums
+            .iter()
+            .map(|album| {
+                article(
+                    format!("album_{}", album.id),
+                    &album.name,
+                    NeteaseLink::Album(album.id).url(),
+                    format!("{} · {} 首", format_artists(&album.artists), album.size),
+                    album.pic_url.as_ref(),
+                )
+            })
+            .collect(),
+        SearchType::Artist => result
+            .artists
+            .iter()
+            .map(|artist| {
+                article(
+                    format!("artist_{}", artist.id),
+                    &artist.name,
+                    NeteaseLink::Artist(artist.id).url(),
+                    format!("{} 张专辑", artist.album_size),
+                    artist.pic_url.as_ref(),
+                )
+            })
+            .collect(),
+        SearchType::Playlist => result
+            .playlists
+            .iter()
+            .map(|playlist| {
+                article(
+                    format!("playlist_{}", playlist.id),
+                    &playlist.name,
+                    NeteaseLink::Playlist(playlist.id).url(),
+                    format!("{} 首", playlist.track_count),
+                    playlist.cover_img_url.as_ref(),
+                )
+            })
+            .collect(),
+        SearchType::User => result
+            .userprofiles
+            .iter()
+            .map(|user| {
+                article(
+                    format!("user_{}", user.user_id),
+                    &user.nickname,
+                    NeteaseLink::User(user.user_id).url(),
+                    user.signature.clone().unwrap_or_default(),
+                    user.avatar_url.as_ref(),
+                )
+            })
+            .collect(),
+        SearchType::Mv => result
+            .mvs
+            .iter()
+            .map(|mv| {
+                article(
+                    format!("mv_{}", mv.id),
+                    &mv.name,
+                    NeteaseLink::Mv(mv.id).url(),
+                    mv.artist_name.clone(),
+                    mv.cover.as_ref(),
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Inline results per page
+const INLINE_PAGE_SIZE: u32 = 10;
+/// Other songs of a result page cached after one of them is chosen
+const INLINE_WARM_SONGS: usize = 3;
+/// Recent songs shown for an empty inline query
+const INLINE_RECENT_SONGS: i64 = 10;
+/// Hot searches shown for an empty inline query
+const INLINE_HOT_SEARCHES: usize = 10;
+/// Suggestions shown above the first page of an inline search
+const INLINE_SUGGESTIONS: usize = 3;
+
+async fn handle_inline_query(query: InlineQuery, state: Arc<BotState>) -> ResponseResult<()> {
+    let bot = state.endpoints.bot();
+    let (search_keyword, is_search_cmd) = strip_search_prefix(query.query.trim());
+
     if search_keyword.is_empty() {
         if is_search_cmd {
             let help_article = InlineQueryResultArticle::new(
@@ -1381,46 +4303,48 @@ async fn handle_inline_query(
             bot.answer_inline_query(&query.id, vec![InlineQueryResult::Article(help_article)])
                 .await?;
         } else {
-            let help_article = InlineQueryResultArticle::new(
-                "usage_help",
-                "如何使用此机器人？",
-                InputMessageContent::Text(InputMessageContentText::new(
-                    "使用方法：\n1. 直接输入关键词搜索音乐\n2. 输入 search 关键词 搜索音乐\n3. 粘贴网易云音乐链接\n4. 输入歌曲 ID".to_string()
-                )),
-            )
-            .description("在输入框中输入关键词开始搜索音乐");
-
-            bot.answer_inline_query(&query.id, vec![InlineQueryResult::Article(help_article)])
+            let results = inline_home_results(&state, &query.from).await;
+            bot.answer_inline_query(&query.id, results)
+                .is_personal(true)
+                .cache_time(30)
                 .await?;
         }
         return Ok(());
     }
 
-    match state.music_api.search_songs(search_keyword, 10).await {
-        Ok(songs) => {
-            let mut results = Vec::new();
-
-            for (i, song) in songs.iter().take(10).enumerate() {
-                let artists = format_artists(&song.artists);
-
-                let mut article = InlineQueryResultArticle::new(
-                    format!("{}_{}", song.id, i),
-                    &song.name,
-                    InputMessageContent::Text(InputMessageContentText::new(format!(
-                        "/netease {}",
-                        song.id
-                    ))),
-                )
-                .description(artists);
-
-                if let Some(ref pic_url) = song.album.pic_url {
-                    article.thumb_url = Some(reqwest::Url::parse(pic_url).unwrap());
+    let (search_type, keyword) = SearchType::parse_query(search_keyword);
+    let offset: u32 = query.offset.parse().unwrap_or(0);
+    match load_inline_page(&state, search_type, keyword, offset).await {
+        Ok(result) => {
+            let mut cached = HashMap::new();
+            for song in &result.songs {
+                if let Some(info) = cached_song(&state, song.id).await {
+                    cached.insert(song.id, info);
                 }
+            }
 
-                results.push(InlineQueryResult::Article(article));
+            let mut results = Vec::new();
+            if offset == 0 && search_type == SearchType::Song {
+                results.extend(inline_suggestions(&state, keyword).await);
             }
+            results.extend(inline_search_results(
+                search_type,
+                &result,
+                offset,
+                &cached,
+                &state.bot_username,
+            ));
+
+            let count = result.len(search_type) as u64;
+            let next_offset = if count > 0 && u64::from(offset) + count < result.total(search_type)
+            {
+                (offset + INLINE_PAGE_SIZE).to_string()
+            } else {
+                String::new()
+            };
 
             bot.answer_inline_query(&query.id, results)
+                .next_offset(next_offset)
                 .cache_time(300)
                 .await?;
         }
@@ -1441,6 +4365,166 @@ async fn handle_inline_query(
     Ok(())
 }
 
+/// Remove the optional "search" prefix of an inline query (kept for consistency with the
+/// Go version). Returns the keyword and whether the prefix was there.
+fn strip_search_prefix(text: &str) -> (&str, bool) {
+    if text.to_lowercase().starts_with("search ") {
+        (text[7..].trim(), true)
+    } else if text.to_lowercase().starts_with("search") {
+        ("", true)
+    } else {
+        (text, false)
+    }
+}
+
+/// Fetch one page of inline results, served from the short-lived cache when possible
+async fn load_inline_page(
+    state: &Arc<BotState>,
+    search_type: SearchType,
+    keyword: &str,
+    offset: u32,
+) -> Result<Arc<SearchResult>> {
+    let key = (search_type, keyword.to_string(), offset);
+    if let Some(result) = state.inline_pages.get(&key) {
+        return Ok(result);
+    }
+
+    let result = Arc::new(
+        state
+            .music_api
+            .search(keyword, search_type, INLINE_PAGE_SIZE, offset)
+            .await?,
+    );
+    state.inline_pages.insert(key, Arc::clone(&result));
+    Ok(result)
+}
+
+/// An article that, when tapped, searches `keyword` inline in the current chat
+fn inline_keyword_article(
+    id: String,
+    title: String,
+    keyword: &str,
+    description: &str,
+) -> InlineQueryResult {
+    let article = InlineQueryResultArticle::new(
+        id,
+        title,
+        InputMessageContent::Text(InputMessageContentText::new(format!("🔍 {keyword}"))),
+    )
+    .description(description)
+    .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::switch_inline_query_current_chat("🔍 搜索", keyword),
+    ]]));
+    InlineQueryResult::Article(article)
+}
+
+/// Results for an empty inline query: the user's recent songs and NetEase hot searches
+async fn inline_home_results(state: &Arc<BotState>, user: &User) -> Vec<InlineQueryResult> {
+    let mut results = Vec::new();
+
+    match state
+        .database
+        .recent_songs(user.id.0 as i64, INLINE_RECENT_SONGS)
+        .await
+    {
+        Ok(songs) => {
+            for (i, song) in songs.iter().enumerate() {
+                if let Some(file_id) = &song.file_id {
+                    results.push(InlineQueryResult::CachedAudio(
+                        InlineQueryResultCachedAudio::new(
+                            format!("{}_r{i}", song.music_id),
+                            file_id,
+                        )
+                        .caption(cached_caption(song, &state.bot_username))
+                        .reply_markup(create_music_keyboard(
+                            song.music_id as u64,
+                            &song.song_name,
+                            &song.song_artists,
+                        )),
+                    ));
+                }
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load recent songs: {}", e),
+    }
+
+    let hot_searches = match state.hot_searches.get(&()) {
+        Some(hot_searches) => Some(hot_searches),
+        None => match state.music_api.get_hot_searches().await {
+            Ok(hot_searches) => {
+                let hot_searches = Arc::new(hot_searches);
+                state.hot_searches.insert((), Arc::clone(&hot_searches));
+                Some(hot_searches)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to load hot searches: {}", e);
+                None
+            }
+        },
+    };
+    for (i, keyword) in hot_searches
+        .iter()
+        .flat_map(|hot| hot.iter())
+        .take(INLINE_HOT_SEARCHES)
+        .enumerate()
+    {
+        results.push(inline_keyword_article(
+            format!("hot_{i}"),
+            format!("🔥 {keyword}"),
+            keyword,
+            "网易云音乐热搜",
+        ));
+    }
+
+    results.push(InlineQueryResult::Article(
+        InlineQueryResultArticle::new(
+            "usage_help",
+            "如何使用此机器人？",
+            InputMessageContent::Text(InputMessageContentText::new(
+                "使用方法：\n1. 直接输入关键词搜索音乐\n2. 输入 search 关键词 搜索音乐\n3. 使用 album: / artist: / playlist: / lyric: 等前缀搜索专辑、歌手、歌单或歌词\n4. 粘贴网易云音乐链接\n5. 输入歌曲 ID".to_string()
+            )),
+        )
+        .description("在输入框中输入关键词开始搜索音乐"),
+    ));
+    results
+}
+
+/// NetEase keyword suggestions for partial input
+async fn inline_suggestions(state: &Arc<BotState>, keyword: &str) -> Vec<InlineQueryResult> {
+    let suggestions = if let Some(suggestions) = state.suggestions.get(&keyword.to_string()) {
+        suggestions
+    } else {
+        match state.music_api.get_search_suggestions(keyword).await {
+            Ok(suggestions) => {
+                let suggestions = Arc::new(suggestions);
+                state
+                    .suggestions
+                    .insert(keyword.to_string(), Arc::clone(&suggestions));
+                suggestions
+            }
+            Err(e) => {
+                tracing::debug!("Failed to load search suggestions: {}", e);
+                return Vec::new();
+            }
+        }
+    };
+
+    suggestions
+        .iter()
+        .filter(|suggestion| !suggestion.eq_ignore_ascii_case(keyword))
+        .take(INLINE_SUGGESTIONS)
+        .enumerate()
+        .map(|(i, suggestion)| {
+            inline_keyword_article(
+                format!("suggest_{i}"),
+                format!("🔎 {suggestion}"),
+                suggestion,
+                "搜索建议",
+            )
+        })
+        .collect()
+}
+
 /// Build caption with exact format:
 /// 「Title」- Artists
 /// 专辑: Album
@@ -1463,3 +4547,41 @@ fn build_caption(
         "「{title}」- {artists}\n专辑: {album}\n#网易云音乐 #{ext} {size_mb:.2}MB {kbps:.2}kbps\nvia @{bot_username}",
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u64, username: &str) -> User {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "is_bot": false,
+            "first_name": username,
+            "username": username,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_callback_download_uses_presser() {
+        // Buttons sit on the bot's own message, so its sender is the bot
+        let bot_message: Message = serde_json::from_value(serde_json::json!({
+            "message_id": 10,
+            "date": 0,
+            "chat": { "id": -100, "type": "supergroup", "title": "Music", "username": "music" },
+            "from": { "id": 1000, "is_bot": true, "first_name": "Bot", "username": "music_bot" },
+            "text": "🔍 搜索结果",
+        }))
+        .unwrap();
+        let presser = user(42, "alice");
+
+        // The bound NetEase account is looked up by `from_user_id`
+        let target = SendTarget::reply(&bot_message, Some(&presser), &bot_message);
+        assert_eq!(target.from_user_id, 42);
+        assert_eq!(target.from_user_name, "alice");
+        assert_eq!(target.chat_id, ChatId(-100));
+
+        let target = SendTarget::reply(&bot_message, bot_message.from(), &bot_message);
+        assert_eq!(target.from_user_id, 1000);
+    }
+}
@@ -4,6 +4,12 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+/// Upload size limit of the official Telegram Bot API (MB)
+pub const OFFICIAL_UPLOAD_LIMIT_MB: u64 = 50;
+
+/// Upload size limit of a self-hosted Bot API server running with `--local` (MB)
+pub const LOCAL_UPLOAD_LIMIT_MB: u64 = 2000;
+
 /// Storage mode for temporary files during download processing
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -49,14 +55,22 @@ impl std::fmt::Display for StorageMode {
 pub struct Config {
     // Required fields
     pub bot_token: String,
-    pub music_u: Option<String>,
+    /// `MUSIC_U` cookies of the accounts requests are spread over
+    pub music_u: Vec<String>,
 
     // Optional fields with defaults
-    pub bot_api: String,
+    /// Telegram Bot API endpoints in priority order
+    pub bot_api: Vec<String>,
     pub music_api: String,
+    /// eapi client profile, see `device::ClientProfile`
+    pub music_appver: Option<String>,
+    pub music_os: Option<String>,
+    pub music_user_agent: Option<String>,
     pub bot_admin: Vec<i64>,
     pub bot_debug: bool,
     pub database: String,
+    /// Secret the bound user credentials are encrypted with, defaults to the bot token
+    pub database_secret: Option<String>,
     pub log_level: String,
     pub cache_dir: String,
     pub auto_update: bool,
@@ -72,18 +86,45 @@ pub struct Config {
     pub memory_threshold_mb: u64,
     /// Memory buffer in MB (available memory must exceed file size + buffer to use memory mode)
     pub memory_buffer_mb: u64,
+
+    /// Maximum upload size in MB (0 = derive from the Bot API server in use)
+    pub upload_limit_mb: u64,
+
+    // Local Bot API server settings
+    /// The custom Bot API server runs with `--local` and accepts `file://` uploads
+    pub local_mode: bool,
+    /// Directory shared with the local Bot API server for in-memory files (defaults to cache_dir)
+    pub local_dir: Option<String>,
+
+    // Webhook settings (long polling is used when webhook_url is not set)
+    /// Public HTTPS URL Telegram should deliver updates to
+    pub webhook_url: Option<String>,
+    /// Local address the embedded HTTP server listens on
+    pub webhook_listen: String,
+    /// Secret token verified on every webhook request (generated when empty)
+    pub webhook_secret: Option<String>,
+
+    /// Maximum number of links handled from a single message
+    pub max_links_per_message: usize,
+
+    /// Chat songs are uploaded to when caching them for inline mode
+    pub cache_chat: Option<i64>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             bot_token: String::new(),
-            music_u: None,
-            bot_api: "https://api.telegram.org".to_string(),
+            music_u: Vec::new(),
+            bot_api: vec!["https://api.telegram.org".to_string()],
             music_api: "https://music.163.com".to_string(),
+            music_appver: None,
+            music_os: None,
+            music_user_agent: None,
             bot_admin: Vec::new(),
             bot_debug: false,
             database: "cache.db".to_string(),
+            database_secret: None,
             log_level: "info".to_string(),
             cache_dir: "./cache".to_string(),
             auto_update: true,
@@ -95,6 +136,14 @@ impl Default for Config {
             storage_mode: StorageMode::Disk, // Backward compatible
             memory_threshold_mb: 100,
             memory_buffer_mb: 100,
+            upload_limit_mb: 0,
+            local_mode: false,
+            local_dir: None,
+            webhook_url: None,
+            webhook_listen: "0.0.0.0:8443".to_string(),
+            webhook_secret: None,
+            max_links_per_message: 3,
+            cache_chat: None,
         }
     }
 }
@@ -150,20 +199,48 @@ impl Config {
             config.bot_token.clone_from(token);
         }
 
-        config.music_u = config_map.get("music.music_u").cloned();
+        if let Some(music_u) = config_map.get("music.music_u") {
+            config.music_u = music_u
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+        }
 
         if let Some(api) = config_map.get("bot.api") {
-            config.bot_api.clone_from(api);
+            let apis: Vec<String> = api
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            if !apis.is_empty() {
+                config.bot_api = apis;
+            }
         }
 
         if let Some(api) = config_map.get("music.api") {
             config.music_api.clone_from(api);
         }
 
+        let music_option = |key: &str| {
+            config_map
+                .get(key)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        config.music_appver = music_option("music.appver");
+        config.music_os = music_option("music.os");
+        config.music_user_agent = music_option("music.user_agent");
+
         if let Some(url) = config_map.get("database.url") {
             config.database.clone_from(url);
         }
 
+        config.database_secret = config_map
+            .get("database.secret")
+            .filter(|secret| !secret.is_empty())
+            .cloned();
+
         if let Some(dir) = config_map.get("download.dir") {
             config.cache_dir.clone_from(dir);
         }
@@ -229,6 +306,62 @@ impl Config {
             config.memory_buffer_mb = buffer.parse().unwrap_or(100);
         }
 
+        if let Some(limit) = config_map.get("bot.upload_limit") {
+            config.upload_limit_mb = limit.parse().unwrap_or(0);
+        }
+
+        if let Some(local_mode) = config_map.get("bot.local_mode") {
+            config.local_mode = local_mode.to_lowercase() == "true";
+        }
+        config.local_dir = config_map
+            .get("bot.local_dir")
+            .filter(|dir| !dir.is_empty())
+            .cloned();
+
+        config.webhook_url = config_map
+            .get("bot.webhook_url")
+            .filter(|url| !url.is_empty())
+            .cloned();
+        if let Some(listen) = config_map.get("bot.webhook_listen") {
+            if !listen.is_empty() {
+                config.webhook_listen.clone_from(listen);
+            }
+        }
+        config.webhook_secret = config_map
+            .get("bot.webhook_secret")
+            .filter(|secret| !secret.is_empty())
+            .cloned();
+        if let Some(secret) = &config.webhook_secret {
+            // Telegram only accepts 1-256 characters from A-Z, a-z, 0-9, _ and -
+            let valid = secret.len() <= 256
+                && secret
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            if !valid {
+                return Err(anyhow::anyhow!(
+                    "webhook_secret may only contain A-Z, a-z, 0-9, _ and - (max 256 characters)"
+                ));
+            }
+        }
+
+        if let Some(max_links) = config_map.get("bot.max_links") {
+            config.max_links_per_message = max_links.parse().unwrap_or(3).max(1);
+        }
+
+        config.cache_chat = config_map
+            .get("bot.cache_chat")
+            .and_then(|chat| chat.parse().ok());
+
+        if config.local_mode
+            && config
+                .bot_api
+                .iter()
+                .all(|api| api.contains("api.telegram.org"))
+        {
+            tracing::warn!("local_mode requires a self-hosted Bot API server, disabling it");
+            config.local_mode = false;
+        }
+
         // Validate required fields
         if config.bot_token.is_empty() {
             return Err(anyhow::anyhow!("BOT_TOKEN is required"));
@@ -236,4 +369,10 @@ impl Config {
 
         Ok(config)
     }
+
+    /// Directory where files handed to the local Bot API server are written
+    #[must_use]
+    pub fn local_dir(&self) -> &str {
+        self.local_dir.as_deref().unwrap_or(&self.cache_dir)
+    }
 }
@@ -0,0 +1,77 @@
+//! Encryption of user credentials at rest
+//!
+//! Cookies users bind to their Telegram account are stored in the database as
+//! hex encoded `nonce || ciphertext` of AES-256-GCM. The key is derived from the
+//! configured secret, so a copy of the database alone does not reveal any cookie.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Nonce};
+use sha2::{Digest, Sha256};
+
+use crate::error::{BotError, Result};
+
+/// Length of the random nonce prepended to every ciphertext
+const NONCE_LEN: usize = 12;
+
+pub struct CredentialCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialCipher {
+    #[must_use]
+    pub fn new(secret: &str) -> Self {
+        let key = Sha256::digest(secret.as_bytes());
+        Self {
+            cipher: Aes256Gcm::new(&key),
+        }
+    }
+
+    /// Encrypt a credential with a fresh nonce
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| BotError::Other(anyhow::anyhow!("Failed to encrypt credential")))?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        Ok(hex::encode(data))
+    }
+
+    /// Decrypt a credential stored by `encrypt`. Fails when the secret has changed.
+    pub fn decrypt(&self, stored: &str) -> Result<String> {
+        let data = hex::decode(stored)
+            .map_err(|e| BotError::Parse(format!("Invalid stored credential: {e}")))?;
+        if data.len() < NONCE_LEN {
+            return Err(BotError::Parse(
+                "Stored credential is too short".to_string(),
+            ));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| BotError::Other(anyhow::anyhow!("Failed to decrypt credential")))?;
+        String::from_utf8(plaintext)
+            .map_err(|e| BotError::Parse(format!("Invalid stored credential: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credential_round_trip() {
+        let cipher = CredentialCipher::new("secret");
+        let stored = cipher.encrypt("MUSIC_U_VALUE").unwrap();
+        assert!(!stored.contains("MUSIC_U_VALUE"));
+        assert_ne!(stored, cipher.encrypt("MUSIC_U_VALUE").unwrap());
+        assert_eq!(cipher.decrypt(&stored).unwrap(), "MUSIC_U_VALUE");
+
+        assert!(CredentialCipher::new("other").decrypt(&stored).is_err());
+        assert!(cipher.decrypt("00ff").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Request encryption of the NetEase API transports
+//!
+//! - weapi (web client): the JSON payload is AES-128-CBC encrypted with a preset key,
+//!   then again with a random 16 character key. The reversed random key is RSA
+//!   encrypted without padding and sent along as `encSecKey`.
+//! - eapi (mobile client): `path` and payload are signed with MD5 and AES-128-ECB
+//!   encrypted; responses may come back encrypted the same way.
+//! - linuxapi (Linux client): the whole request is AES-128-ECB encrypted and sent to
+//!   a forwarding endpoint.
+
+use aes::Aes128;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyInit, KeyIvInit};
+use md5::compute as md5_compute;
+use num_bigint::BigUint;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use crate::error::{BotError, Result};
+
+const WEAPI_PRESET_KEY: &[u8; 16] = b"0CoJUm6Qyw8W8jud";
+const WEAPI_IV: &[u8; 16] = b"0102030405060708";
+const WEAPI_PUBLIC_EXPONENT: u32 = 0x10001;
+const WEAPI_MODULUS: &str = "00e0b509f6259df8642dbc35662901477df22677ec152b5ff68ace615bb7b725152b3ab17a876aea8a5aa76d2e417629ec4ee341f56135fccf695280104e0312ecbda92557c93870114af6c9d05c4f7f0c3685b7a46bee255932575cce10b424d813cfe4875d3e82047b97ddef52741d546b8e289dc6935b3ece0462db0a22b8e7";
+const EAPI_KEY: &[u8; 16] = b"e82ckenh8dichen8";
+const LINUXAPI_KEY: &[u8; 16] = b"rFgB&h#%2?^eDg:Q";
+
+fn aes_ecb_encrypt(key: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    ecb::Encryptor::<Aes128>::new(key.into()).encrypt_padded_vec_mut::<Pkcs7>(data)
+}
+
+fn aes_cbc_encrypt(key: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    cbc::Encryptor::<Aes128>::new(key.into(), WEAPI_IV.into()).encrypt_padded_vec_mut::<Pkcs7>(data)
+}
+
+/// Form fields of a weapi request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WeapiForm {
+    pub params: String,
+    pub enc_sec_key: String,
+}
+
+/// Encrypt a weapi payload with a random secret key
+#[must_use]
+pub fn weapi(text: &str) -> WeapiForm {
+    let secret_key: Vec<u8> = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .collect();
+    weapi_with_key(text, secret_key.as_slice().try_into().expect("16 byte key"))
+}
+
+fn weapi_with_key(text: &str, secret_key: &[u8; 16]) -> WeapiForm {
+    let first = STANDARD.encode(aes_cbc_encrypt(WEAPI_PRESET_KEY, text.as_bytes()));
+    let params = STANDARD.encode(aes_cbc_encrypt(secret_key, first.as_bytes()));
+
+    let reversed: Vec<u8> = secret_key.iter().rev().copied().collect();
+    let modulus = BigUint::parse_bytes(WEAPI_MODULUS.as_bytes(), 16).expect("weapi modulus");
+    let encrypted =
+        BigUint::from_bytes_be(&reversed).modpow(&BigUint::from(WEAPI_PUBLIC_EXPONENT), &modulus);
+
+    WeapiForm {
+        params,
+        enc_sec_key: format!("{:0>256}", encrypted.to_str_radix(16)),
+    }
+}
+
+/// Sign and encrypt an eapi payload for `path` (the `/api/...` path of the endpoint).
+/// Returns the upper case hex `params` form field.
+#[must_use]
+pub fn eapi(path: &str, json: &str) -> String {
+    let marker = "36cd479b6b5";
+    let text = format!("nobody{path}use{json}md5forencrypt");
+    let digest = format!("{:x}", md5_compute(text.as_bytes()));
+    let data = format!("{path}-{marker}-{json}-{marker}-{digest}");
+    hex::encode_upper(aes_ecb_encrypt(EAPI_KEY, data.as_bytes()))
+}
+
+/// Decrypt an encrypted eapi response body
+pub fn eapi_decrypt(hex_data: &str) -> Result<String> {
+    let bytes = hex::decode(hex_data.trim()).map_err(|e| BotError::MusicApi(e.to_string()))?;
+    let decrypted = ecb::Decryptor::<Aes128>::new(EAPI_KEY.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(&bytes)
+        .map_err(|e| BotError::MusicApi(e.to_string()))?;
+    String::from_utf8(decrypted).map_err(|e| BotError::MusicApi(e.to_string()))
+}
+
+/// Encrypt a linuxapi request (`{"method", "url", "params"}` JSON).
+/// Returns the upper case hex `eparams` form field.
+#[must_use]
+pub fn linuxapi(text: &str) -> String {
+    hex::encode_upper(aes_ecb_encrypt(LINUXAPI_KEY, text.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Expected values were computed with an independent AES/RSA implementation
+
+    #[test]
+    fn test_weapi() {
+        let form = weapi_with_key(r#"{"id":186016}"#, b"0123456789abcdef");
+        assert_eq!(form.params, "plWQMsvvo6QCcLfH2tHG2wQcAT3AkR5r+hUvE7vR8vo=");
+        assert_eq!(
+            form.enc_sec_key,
+            "35701388baf89fed412e11269b9c76625d095ecaf17f03fa018abe19ea2d38b949debf242ee39a71ca1f6cda71b1b86a45aa909ee27f7e78e267d34e732f0de948206c3340a788d0003372183e2f753c1f78b66ac23d134ac1fc9b993156520ea826b8aa89a962d4491b4b8d7e08738e1da9b07aa39bf4a7ef0b1c210728cd52"
+        );
+
+        // The well known key pair used by many NetEase clients
+        let form = weapi_with_key("{}", b"FFFFFFFFFFFFFFFF");
+        assert_eq!(
+            form.enc_sec_key,
+            "257348aecb5e556c066de214e531faadd1c55d814f9be95fd06d6bff9f4c7a41f831f6394d5a3fd2e3881736d94a02ca919d952872e7d0a50ebfa1769a7a62d512f5f1ca21aec60bc3819a9c3ffca5eca9a0dba6d6f7249b06f5965ecfff3695b54e1c28f3f624750ed39e7de08fc8493242e26dbc4484a01c76f739e135637c"
+        );
+
+        let form = weapi("{}");
+        assert_eq!(form.enc_sec_key.len(), 256);
+        assert_ne!(form, weapi("{}"));
+    }
+
+    #[test]
+    fn test_eapi() {
+        let params = eapi("/api/song/lyric", r#"{"id":186016}"#);
+        assert_eq!(
+            params,
+            "9B1595479B79266065903BA79E9883029338432CD7D98D1A3626E997A2C531212C5FC514D37F364E7A0F0AEDD0007652CFD4591ED7EB7E1F72D4A9F0737DCCFE61733D5B362C9AED482B286051892A22519EB67E4EF8528CC0F1C79C38AD4DC1"
+        );
+
+        let response = hex::encode_upper(aes_ecb_encrypt(EAPI_KEY, br#"{"code":200}"#));
+        assert_eq!(eapi_decrypt(&response).unwrap(), r#"{"code":200}"#);
+        assert!(eapi_decrypt("not hex").is_err());
+    }
+
+    #[test]
+    fn test_linuxapi() {
+        let text = r#"{"method":"POST","url":"https://music.163.com/api/song/lyric","params":{"id":186016}}"#;
+        assert_eq!(
+            linuxapi(text),
+            "A0D9583F4C5FF68DE851D2893A49DE98FAFB24399F27B4F7E74C64B6FC49A965CFA972FA5EA3D6247CD6247C8198CB876BA9315A2F4B205B4E87A713A33C1C5D7A74B15311AFBDC959E41C13D1B5EF49ED1F6E843AD671DF48C19A7703CCC3D3"
+        );
+    }
+}
@@ -1,6 +1,7 @@
 use crate::error::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqliteRow;
 use sqlx::{Row, SqlitePool};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -26,6 +27,10 @@ pub struct SongInfo {
     pub updated_at: DateTime<Utc>,
 }
 
+/// Requests kept per user in `song_history`
+const HISTORY_PER_USER: i64 = 200;
+
+#[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
 }
@@ -71,6 +76,108 @@ impl Database {
         .execute(&pool)
         .await?;
 
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS song_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                chat_id INTEGER NOT NULL,
+                music_id INTEGER NOT NULL,
+                played_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_song_history_user ON song_history (user_id, music_id)",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS chat_settings (
+                chat_id INTEGER PRIMARY KEY,
+                auto_search INTEGER NOT NULL DEFAULT 1,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS radio_sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                chat_id INTEGER NOT NULL,
+                seed_music_id INTEGER NOT NULL,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS radio_tracks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL,
+                music_id INTEGER NOT NULL,
+                played_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (session_id, music_id)
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS netease_accounts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                music_u TEXT UNIQUE NOT NULL,
+                user_id INTEGER UNIQUE,
+                nickname TEXT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS user_accounts (
+                user_id INTEGER PRIMARY KEY,
+                credential TEXT NOT NULL,
+                nickname TEXT,
+                stale INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
+        sqlx::query(
+            r"
+            CREATE TABLE IF NOT EXISTS device_identity (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                device_id TEXT NOT NULL,
+                music_a TEXT,
+                updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )
+            ",
+        )
+        .execute(&pool)
+        .await?;
+
         Ok(Self { pool })
     }
 
@@ -81,39 +188,7 @@ impl Database {
             .fetch_optional(&self.pool)
             .await?;
 
-        match row {
-            Some(row) => {
-                let song_info = SongInfo {
-                    id: row.get("id"),
-                    music_id: row.get("music_id"),
-                    song_name: row.get("song_name"),
-                    song_artists: row.get("song_artists"),
-                    song_album: row.get("song_album"),
-                    file_ext: row.get("file_ext"),
-                    music_size: row.get("music_size"),
-                    pic_size: row.get("pic_size"),
-                    emb_pic_size: row.get("emb_pic_size"),
-                    bit_rate: row.get("bit_rate"),
-                    duration: row.get("duration"),
-                    file_id: row.get("file_id"),
-                    thumb_file_id: row.get("thumb_file_id"),
-                    from_user_id: row.get("from_user_id"),
-                    from_user_name: row.get("from_user_name"),
-                    from_chat_id: row.get("from_chat_id"),
-                    from_chat_name: row.get("from_chat_name"),
-                    created_at: row
-                        .get::<String, _>("created_at")
-                        .parse()
-                        .unwrap_or_else(|_| Utc::now()),
-                    updated_at: row
-                        .get::<String, _>("updated_at")
-                        .parse()
-                        .unwrap_or_else(|_| Utc::now()),
-                };
-                Ok(Some(song_info))
-            }
-            None => Ok(None),
-        }
+        Ok(row.as_ref().map(song_info_from_row))
     }
 
     /// Save or update song info
@@ -221,4 +296,426 @@ impl Database {
 
         Ok(result.rows_affected() > 0)
     }
+
+    /// Whether plain text and mentions trigger a search in a chat (enabled by default)
+    pub async fn is_auto_search_enabled(&self, chat_id: i64) -> Result<bool> {
+        let row = sqlx::query("SELECT auto_search FROM chat_settings WHERE chat_id = ?")
+            .bind(chat_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_none_or(|row| row.get::<bool, _>("auto_search")))
+    }
+
+    /// Enable or disable plain text search in a chat
+    pub async fn set_auto_search(&self, chat_id: i64, enabled: bool) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO chat_settings (chat_id, auto_search, updated_at)
+            VALUES (?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                auto_search = excluded.auto_search,
+                updated_at = CURRENT_TIMESTAMP
+            ",
+        )
+        .bind(chat_id)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remember that a user requested a song, keeping only their latest
+    /// `HISTORY_PER_USER` requests
+    pub async fn record_history(&self, user_id: i64, chat_id: i64, music_id: i64) -> Result<()> {
+        sqlx::query("INSERT INTO song_history (user_id, chat_id, music_id) VALUES (?, ?, ?)")
+            .bind(user_id)
+            .bind(chat_id)
+            .bind(music_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            r"
+            DELETE FROM song_history
+            WHERE user_id = ? AND id NOT IN (
+                SELECT id FROM song_history WHERE user_id = ? ORDER BY id DESC LIMIT ?
+            )
+            ",
+        )
+        .bind(user_id)
+        .bind(user_id)
+        .bind(HISTORY_PER_USER)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Cached songs a user requested most recently, newest first
+    pub async fn recent_songs(&self, user_id: i64, limit: i64) -> Result<Vec<SongInfo>> {
+        let rows = sqlx::query(
+            r"
+            SELECT s.* FROM song_infos s
+            JOIN (
+                SELECT music_id, MAX(id) AS last_id FROM song_history
+                WHERE user_id = ?
+                GROUP BY music_id
+            ) h ON h.music_id = s.music_id
+            WHERE s.file_id IS NOT NULL
+            ORDER BY h.last_id DESC
+            LIMIT ?
+            ",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(song_info_from_row).collect())
+    }
+
+    /// Store a NetEase login cookie. With a `user_id` the cookie replaces any
+    /// other one stored for the same NetEase user.
+    pub async fn save_account(
+        &self,
+        music_u: &str,
+        user_id: Option<i64>,
+        nickname: Option<&str>,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        if let Some(user_id) = user_id {
+            sqlx::query("DELETE FROM netease_accounts WHERE user_id = ? AND music_u != ?")
+                .bind(user_id)
+                .bind(music_u)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            r"
+            INSERT INTO netease_accounts (music_u, user_id, nickname, created_at, updated_at)
+            VALUES (?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+            ON CONFLICT(music_u) DO UPDATE SET
+                user_id = COALESCE(excluded.user_id, user_id),
+                nickname = COALESCE(excluded.nickname, nickname),
+                updated_at = CURRENT_TIMESTAMP
+            ",
+        )
+        .bind(music_u)
+        .bind(user_id)
+        .bind(nickname)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Store a renewed login cookie of `user_id` in place of the one it replaces
+    pub async fn replace_account(
+        &self,
+        old_music_u: &str,
+        music_u: &str,
+        user_id: i64,
+        nickname: Option<&str>,
+    ) -> Result<()> {
+        self.save_account(music_u, Some(user_id), nickname).await?;
+        sqlx::query("DELETE FROM netease_accounts WHERE music_u = ?")
+            .bind(old_music_u)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Forget a stored login cookie
+    pub async fn delete_account(&self, music_u: &str) -> Result<()> {
+        sqlx::query("DELETE FROM netease_accounts WHERE music_u = ?")
+            .bind(music_u)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// All stored login cookies, oldest first
+    pub async fn accounts(&self) -> Result<Vec<String>> {
+        let rows = sqlx::query("SELECT music_u FROM netease_accounts ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.into_iter().map(|row| row.get("music_u")).collect())
+    }
+
+    /// Bind an encrypted NetEase credential to a Telegram user, replacing any earlier one
+    pub async fn bind_user_account(
+        &self,
+        user_id: i64,
+        credential: &str,
+        nickname: Option<&str>,
+    ) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO user_accounts (user_id, credential, nickname, created_at, updated_at)
+            VALUES (?, ?, ?, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+            ON CONFLICT(user_id) DO UPDATE SET
+                credential = excluded.credential,
+                nickname = excluded.nickname,
+                stale = 0,
+                updated_at = CURRENT_TIMESTAMP
+            ",
+        )
+        .bind(user_id)
+        .bind(credential)
+        .bind(nickname)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Encrypted credential and nickname bound to a Telegram user, unless it went stale
+    pub async fn user_account(&self, user_id: i64) -> Result<Option<(String, Option<String>)>> {
+        let row = sqlx::query(
+            "SELECT credential, nickname FROM user_accounts WHERE user_id = ? AND stale = 0",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| (row.get("credential"), row.get("nickname"))))
+    }
+
+    /// Stop using the credential of a Telegram user after NetEase logged it out.
+    /// Returns whether it was in use until now.
+    pub async fn mark_user_account_stale(&self, user_id: i64) -> Result<bool> {
+        let result = sqlx::query(
+            r"
+            UPDATE user_accounts SET stale = 1, updated_at = CURRENT_TIMESTAMP
+            WHERE user_id = ? AND stale = 0
+            ",
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove the credential bound to a Telegram user. Returns whether there was one.
+    pub async fn unbind_user_account(&self, user_id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM user_accounts WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Device id and anonymous token the bot presents to NetEase
+    pub async fn device_identity(&self) -> Result<Option<(String, Option<String>)>> {
+        let row = sqlx::query("SELECT device_id, music_a FROM device_identity WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| (row.get("device_id"), row.get("music_a"))))
+    }
+
+    /// Store the device identity, replacing the previous one
+    pub async fn save_device_identity(&self, device_id: &str, music_a: Option<&str>) -> Result<()> {
+        sqlx::query(
+            r"
+            INSERT INTO device_identity (id, device_id, music_a, updated_at)
+            VALUES (1, ?, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(id) DO UPDATE SET
+                device_id = excluded.device_id,
+                music_a = excluded.music_a,
+                updated_at = CURRENT_TIMESTAMP
+            ",
+        )
+        .bind(device_id)
+        .bind(music_a)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Start a radio session in a chat, with the seed song as its first track
+    pub async fn create_radio_session(&self, chat_id: i64, seed_music_id: i64) -> Result<i64> {
+        let result =
+            sqlx::query("INSERT INTO radio_sessions (chat_id, seed_music_id) VALUES (?, ?)")
+                .bind(chat_id)
+                .bind(seed_music_id)
+                .execute(&self.pool)
+                .await?;
+        let session_id = result.last_insert_rowid();
+        self.add_radio_track(session_id, seed_music_id).await?;
+
+        Ok(session_id)
+    }
+
+    /// Chat a radio session belongs to, `None` once it has been stopped
+    pub async fn radio_session_chat(&self, session_id: i64) -> Result<Option<i64>> {
+        let row = sqlx::query("SELECT chat_id FROM radio_sessions WHERE id = ?")
+            .bind(session_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|row| row.get("chat_id")))
+    }
+
+    /// Remember that a song was played in a radio session
+    pub async fn add_radio_track(&self, session_id: i64, music_id: i64) -> Result<()> {
+        sqlx::query("INSERT OR IGNORE INTO radio_tracks (session_id, music_id) VALUES (?, ?)")
+            .bind(session_id)
+            .bind(music_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Songs played in a radio session, newest first
+    pub async fn radio_tracks(&self, session_id: i64) -> Result<Vec<i64>> {
+        let rows =
+            sqlx::query("SELECT music_id FROM radio_tracks WHERE session_id = ? ORDER BY id DESC")
+                .bind(session_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(rows.iter().map(|row| row.get("music_id")).collect())
+    }
+
+    /// Stop a radio session and forget its tracks
+    pub async fn delete_radio_session(&self, session_id: i64) -> Result<()> {
+        sqlx::query("DELETE FROM radio_tracks WHERE session_id = ?")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM radio_sessions WHERE id = ?")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn song_info_from_row(row: &SqliteRow) -> SongInfo {
+    SongInfo {
+        id: row.get("id"),
+        music_id: row.get("music_id"),
+        song_name: row.get("song_name"),
+        song_artists: row.get("song_artists"),
+        song_album: row.get("song_album"),
+        file_ext: row.get("file_ext"),
+        music_size: row.get("music_size"),
+        pic_size: row.get("pic_size"),
+        emb_pic_size: row.get("emb_pic_size"),
+        bit_rate: row.get("bit_rate"),
+        duration: row.get("duration"),
+        file_id: row.get("file_id"),
+        thumb_file_id: row.get("thumb_file_id"),
+        from_user_id: row.get("from_user_id"),
+        from_user_name: row.get("from_user_name"),
+        from_chat_id: row.get("from_chat_id"),
+        from_chat_name: row.get("from_chat_name"),
+        created_at: row
+            .get::<String, _>("created_at")
+            .parse()
+            .unwrap_or_else(|_| Utc::now()),
+        updated_at: row
+            .get::<String, _>("updated_at")
+            .parse()
+            .unwrap_or_else(|_| Utc::now()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_database(name: &str) -> (Database, std::path::PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("music163bot-{}-{name}.db", std::process::id()));
+        std::fs::File::create(&path).unwrap();
+        let database = Database::new(path.to_str().unwrap()).await.unwrap();
+        (database, path)
+    }
+
+    #[tokio::test]
+    async fn test_renewed_account_replaces_same_user() {
+        let (database, path) = temp_database("accounts").await;
+
+        // A configured cookie is renewed again on every start
+        database
+            .replace_account("config", "renewed-1", 42, Some("a"))
+            .await
+            .unwrap();
+        database
+            .replace_account("config", "renewed-2", 42, Some("a"))
+            .await
+            .unwrap();
+        assert_eq!(database.accounts().await.unwrap(), vec!["renewed-2"]);
+
+        // Cookies of other users and without a known user are kept
+        database.save_account("other", Some(7), None).await.unwrap();
+        database.save_account("unknown", None, None).await.unwrap();
+        database
+            .replace_account("renewed-2", "renewed-3", 42, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            database.accounts().await.unwrap(),
+            vec!["other", "unknown", "renewed-3"]
+        );
+
+        // Saving a cookie again keeps the user it belongs to
+        database
+            .save_account("renewed-3", None, None)
+            .await
+            .unwrap();
+        database
+            .save_account("renewed-4", Some(42), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            database.accounts().await.unwrap(),
+            vec!["other", "unknown", "renewed-4"]
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_stale_user_account() {
+        let (database, path) = temp_database("user-accounts").await;
+        database
+            .bind_user_account(1, "secret", Some("a"))
+            .await
+            .unwrap();
+
+        // A logged out binding is reported once and no longer used
+        assert!(database.mark_user_account_stale(1).await.unwrap());
+        assert!(!database.mark_user_account_stale(1).await.unwrap());
+        assert!(database.user_account(1).await.unwrap().is_none());
+
+        // Binding again brings it back, unbinding removes it either way
+        database
+            .bind_user_account(1, "renewed", None)
+            .await
+            .unwrap();
+        assert_eq!(
+            database.user_account(1).await.unwrap(),
+            Some(("renewed".to_string(), None))
+        );
+        database.mark_user_account_stale(1).await.unwrap();
+        assert!(database.unbind_user_account(1).await.unwrap());
+
+        std::fs::remove_file(path).ok();
+    }
 }
@@ -0,0 +1,193 @@
+//! Client identity used for eapi requests
+//!
+//! NetEase fingerprints app clients by their `deviceId`, app version and OS. The bot
+//! keeps one device in the database instead of making up a new one per request, and
+//! registers it anonymously for the `MUSIC_A` token sent by requests without a login.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::database::Database;
+use crate::music_api::MusicApi;
+
+pub const DEFAULT_APPVER: &str = "9.3.40";
+pub const DEFAULT_OS: &str = "Android";
+/// Device part of the app user agent
+const DEVICE_USER_AGENT: &str = "Dalvik/2.1.0 (Linux; U; Android 9; MIX 2 MIUI/V12.0.1.0.PDECNXM)";
+
+/// Key the NetEase client XORs the device id with before hashing it
+const DEVICE_ID_XOR_KEY: &[u8] = b"3go8&$8*3*3h0k(2)2";
+
+/// App version, OS and user agent the bot presents itself with
+#[derive(Debug, Clone)]
+pub struct ClientProfile {
+    pub appver: String,
+    pub os: String,
+    pub user_agent: String,
+}
+
+impl Default for ClientProfile {
+    fn default() -> Self {
+        Self {
+            appver: DEFAULT_APPVER.to_string(),
+            os: DEFAULT_OS.to_string(),
+            user_agent: user_agent(DEFAULT_APPVER),
+        }
+    }
+}
+
+impl ClientProfile {
+    /// The configured profile. Without `music.user_agent` the user agent follows the
+    /// configured app version, so both tell NetEase the same.
+    #[must_use]
+    pub fn from_config(config: &Config) -> Self {
+        let appver = config
+            .music_appver
+            .clone()
+            .unwrap_or_else(|| DEFAULT_APPVER.to_string());
+        Self {
+            user_agent: config
+                .music_user_agent
+                .clone()
+                .unwrap_or_else(|| user_agent(&appver)),
+            os: config
+                .music_os
+                .clone()
+                .unwrap_or_else(|| DEFAULT_OS.to_string()),
+            appver,
+        }
+    }
+}
+
+/// User agent of the NetEase app at version `appver`
+#[must_use]
+pub fn user_agent(appver: &str) -> String {
+    format!("NeteaseMusic/{appver};{DEVICE_USER_AGENT}")
+}
+
+/// The device all eapi requests come from
+#[derive(Debug, Clone)]
+pub struct DeviceIdentity {
+    pub device_id: String,
+    /// Anonymous token, `None` until the device has been registered
+    pub music_a: Option<String>,
+}
+
+impl DeviceIdentity {
+    /// A new unregistered device
+    #[must_use]
+    pub fn generate() -> Self {
+        Self {
+            device_id: Uuid::new_v4().simple().to_string().to_uppercase(),
+            music_a: None,
+        }
+    }
+}
+
+/// `username` of the anonymous registration: the device id followed by the base64
+/// MD5 of the device id XORed with `DEVICE_ID_XOR_KEY`, all base64 encoded
+#[must_use]
+pub fn anonymous_username(device_id: &str) -> String {
+    let xored: Vec<u8> = device_id
+        .bytes()
+        .zip(DEVICE_ID_XOR_KEY.iter().cycle())
+        .map(|(byte, key)| byte ^ key)
+        .collect();
+    let digest = STANDARD.encode(md5::compute(&xored).0);
+    STANDARD.encode(format!("{device_id} {digest}"))
+}
+
+/// Load the device from the database, creating it on first start, and register it
+/// anonymously if that has not happened yet. A failed registration is retried by the
+/// first eapi request, and its token stored once it arrives.
+pub async fn init_device(api: &MusicApi, database: &Database) {
+    let mut identity = match database.device_identity().await {
+        Ok(Some((device_id, music_a))) => DeviceIdentity { device_id, music_a },
+        Ok(None) => {
+            let identity = DeviceIdentity::generate();
+            if let Err(e) = database
+                .save_device_identity(&identity.device_id, None)
+                .await
+            {
+                tracing::warn!("Failed to store NetEase device: {}", e);
+            }
+            tracing::info!("Created NetEase device {}", identity.device_id);
+            identity
+        }
+        Err(e) => {
+            tracing::warn!("Failed to load NetEase device: {}", e);
+            DeviceIdentity::generate()
+        }
+    };
+
+    if identity.music_a.is_none() {
+        match api.register_anonymous(&identity.device_id).await {
+            Ok(music_a) => {
+                if let Err(e) = database
+                    .save_device_identity(&identity.device_id, Some(&music_a))
+                    .await
+                {
+                    tracing::warn!("Failed to store anonymous NetEase token: {}", e);
+                }
+                identity.music_a = Some(music_a);
+                tracing::info!("Registered NetEase device {}", identity.device_id);
+            }
+            Err(e) => tracing::warn!("Failed to register NetEase device anonymously: {}", e),
+        }
+    }
+
+    api.set_device(identity);
+
+    let mut updates = api.subscribe_device();
+    let database = database.clone();
+    tokio::spawn(async move {
+        while updates.changed().await.is_ok() {
+            let identity = updates.borrow_and_update().clone();
+            if let Err(e) = database
+                .save_device_identity(&identity.device_id, identity.music_a.as_deref())
+                .await
+            {
+                tracing::warn!("Failed to store anonymous NetEase token: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent_follows_appver() {
+        let config = Config {
+            music_appver: Some("9.1.0".to_string()),
+            ..Config::default()
+        };
+        let profile = ClientProfile::from_config(&config);
+        assert!(profile.user_agent.starts_with("NeteaseMusic/9.1.0;"));
+
+        let config = Config {
+            music_user_agent: Some("Custom".to_string()),
+            ..config
+        };
+        assert_eq!(ClientProfile::from_config(&config).user_agent, "Custom");
+        assert!(ClientProfile::default()
+            .user_agent
+            .starts_with(&format!("NeteaseMusic/{DEFAULT_APPVER};")));
+    }
+
+    #[test]
+    fn test_anonymous_username() {
+        assert_eq!(
+            anonymous_username("0123456789ABCDEF"),
+            "MDEyMzQ1Njc4OUFCQ0RFRiBHNVB4TVhuaE5kN0x6QmtvT2JNNjd3PT0="
+        );
+        // Device ids longer than the key wrap around it
+        assert_eq!(
+            anonymous_username("D9F4B5B3A6C84E2F9A1E0C7B3D5F8A21"),
+            "RDlGNEI1QjNBNkM4NEUyRjlBMUUwQzdCM0Q1RjhBMjEgaU5VclNDUUVreWgwRVNQSHcwV2xaZz09"
+        );
+    }
+}
@@ -0,0 +1,498 @@
+//! Telegram Bot API endpoint pool
+//!
+//! Keeps one general-purpose and one upload-optimized `Bot` per configured endpoint,
+//! probes every endpoint with `get_me` in the background and fails over with a
+//! simple circuit breaker:
+//! - Closed: requests go to the endpoint as usual
+//! - Open: after `FAILURE_THRESHOLD` consecutive failures the endpoint is skipped
+//! - Half-open: once `OPEN_DURATION` has passed, the next probe decides its fate
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use teloxide::prelude::*;
+use teloxide::RequestError;
+use tokio::sync::watch;
+
+use crate::config::{Config, LOCAL_UPLOAD_LIMIT_MB, OFFICIAL_UPLOAD_LIMIT_MB};
+
+/// Official Telegram Bot API server
+pub const OFFICIAL_API_URL: &str = "https://api.telegram.org";
+
+/// Consecutive failures before an endpoint is taken out of rotation
+const FAILURE_THRESHOLD: u32 = 3;
+/// How long an endpoint stays out of rotation before it is probed again
+const OPEN_DURATION: Duration = Duration::from_mins(1);
+/// Interval between background health probes
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+/// Timeout of a single `get_me` probe
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Default)]
+struct Health {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    last_error: Option<String>,
+    last_latency: Option<Duration>,
+}
+
+/// A single Bot API server
+pub struct ApiEndpoint {
+    pub url: reqwest::Url,
+    /// Server runs with `--local` and accepts `file://` uploads
+    pub local: bool,
+    /// Maximum upload size in bytes accepted by this server
+    pub upload_limit: u64,
+    bot: Bot,
+    upload_bot: Bot,
+    health: Mutex<Health>,
+}
+
+impl ApiEndpoint {
+    fn new(config: &Config, url: reqwest::Url) -> Self {
+        let official = url.host_str() == Some("api.telegram.org");
+        let local = config.local_mode && !official;
+
+        let bot = if official {
+            Bot::new(&config.bot_token)
+        } else {
+            // Custom HTTP client tuned for Cloudflare compatibility (mimic Go http client)
+            let client = reqwest::Client::builder()
+                .use_rustls_tls()
+                .user_agent("Go-http-client/2.0")
+                .pool_max_idle_per_host(0)
+                .timeout(Duration::from_secs(30))
+                .no_gzip()
+                .build()
+                .unwrap();
+            Bot::with_client(&config.bot_token, client).set_api_url(url.clone())
+        };
+
+        // Client optimized for multipart uploads
+        let mut upload_client = reqwest::Client::builder()
+            .use_rustls_tls()
+            .timeout(Duration::from_mins(5)) // large files need longer timeouts
+            .pool_max_idle_per_host(0)
+            .no_gzip(); // avoid gzip interference on multipart boundaries via proxies
+        if !official {
+            upload_client = upload_client.user_agent("Go-http-client/2.0");
+        }
+        let upload_bot = Bot::with_client(&config.bot_token, upload_client.build().unwrap())
+            .set_api_url(url.clone());
+
+        let limit_mb = if config.upload_limit_mb > 0 {
+            config.upload_limit_mb
+        } else if local {
+            LOCAL_UPLOAD_LIMIT_MB
+        } else {
+            OFFICIAL_UPLOAD_LIMIT_MB
+        };
+
+        Self {
+            url,
+            local,
+            upload_limit: limit_mb * 1024 * 1024,
+            bot,
+            upload_bot,
+            health: Mutex::new(Health::default()),
+        }
+    }
+
+    /// General-purpose bot for this endpoint
+    pub fn bot(&self) -> Bot {
+        self.bot.clone()
+    }
+
+    /// Upload-optimized bot for this endpoint
+    pub fn upload_bot(&self) -> Bot {
+        self.upload_bot.clone()
+    }
+
+    /// Whether the circuit breaker lets requests through
+    #[must_use]
+    pub fn is_available(&self) -> bool {
+        let health = self.health.lock().unwrap();
+        health
+            .open_until
+            .is_none_or(|open_until| Instant::now() >= open_until)
+    }
+
+    /// Record a successful request
+    pub fn record_success(&self, latency: Option<Duration>) {
+        let mut health = self.health.lock().unwrap();
+        if health.open_until.is_some() {
+            tracing::info!("✅ Telegram API endpoint recovered: {}", self.url);
+        }
+        health.consecutive_failures = 0;
+        health.open_until = None;
+        health.last_error = None;
+        if latency.is_some() {
+            health.last_latency = latency;
+        }
+    }
+
+    /// Record a failed request, opening the circuit after too many failures in a row
+    pub fn record_failure(&self, error: &str) {
+        let mut health = self.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        health.last_error = Some(error.to_string());
+        if health.consecutive_failures >= FAILURE_THRESHOLD {
+            if health.open_until.is_none() {
+                tracing::warn!(
+                    "❌ Telegram API endpoint {} failed {} times in a row, taking it out of rotation: {}",
+                    self.url,
+                    health.consecutive_failures,
+                    error
+                );
+            }
+            health.open_until = Some(Instant::now() + OPEN_DURATION);
+        }
+    }
+
+    /// Probe the endpoint with `get_me`
+    pub async fn probe(&self) -> bool {
+        let started = Instant::now();
+        match tokio::time::timeout(PROBE_TIMEOUT, self.bot.get_me()).await {
+            Ok(Ok(_)) => {
+                self.record_success(Some(started.elapsed()));
+                true
+            }
+            Ok(Err(e)) => {
+                let error_msg = e.to_string();
+                // Check if it's a CloudFlare challenge or other blocking issue
+                if error_msg.contains("Just a moment")
+                    || error_msg.contains("cloudflare")
+                    || error_msg.contains("challenge")
+                {
+                    self.record_failure("blocked by CloudFlare protection");
+                } else {
+                    self.record_failure(&error_msg);
+                }
+                false
+            }
+            Err(_) => {
+                self.record_failure("timeout");
+                false
+            }
+        }
+    }
+
+    /// Short human readable health summary
+    #[must_use]
+    pub fn health_summary(&self) -> String {
+        let health = self.health.lock().unwrap();
+        let state = if health
+            .open_until
+            .is_some_and(|open_until| Instant::now() < open_until)
+        {
+            "熔断"
+        } else if health.consecutive_failures > 0 {
+            "异常"
+        } else {
+            "正常"
+        };
+        match (&health.last_error, health.last_latency) {
+            (Some(error), _) => format!("{state}: {error}"),
+            (None, Some(latency)) => format!("{state}, {}ms", latency.as_millis()),
+            (None, None) => state.to_string(),
+        }
+    }
+}
+
+/// Ordered set of Bot API endpoints; earlier endpoints are preferred
+pub struct EndpointPool {
+    endpoints: Vec<ApiEndpoint>,
+    /// Index of the current endpoint; receivers see every failover
+    current: watch::Sender<usize>,
+}
+
+impl EndpointPool {
+    /// Build the pool from `[bot] api`. The official API is always kept as the last resort.
+    #[must_use]
+    pub fn new(config: &Config) -> Self {
+        let mut endpoints: Vec<ApiEndpoint> = Vec::new();
+
+        for api in &config.bot_api {
+            // Normalize API URL (ensure it ends with /bot)
+            let api_url_str = if api.ends_with("/bot") {
+                api.clone()
+            } else {
+                format!("{}/bot", api.trim_end_matches('/'))
+            };
+
+            match reqwest::Url::parse(&api_url_str) {
+                Ok(url) => {
+                    if endpoints.iter().all(|e| e.url != url) {
+                        endpoints.push(ApiEndpoint::new(config, url));
+                    }
+                }
+                Err(e) => tracing::error!("Invalid Telegram API URL '{}': {}. Skipping.", api, e),
+            }
+        }
+
+        if endpoints
+            .iter()
+            .all(|e| e.url.host_str() != Some("api.telegram.org"))
+        {
+            let url = reqwest::Url::parse(&format!("{OFFICIAL_API_URL}/bot")).unwrap();
+            endpoints.push(ApiEndpoint::new(config, url));
+        }
+
+        Self {
+            endpoints,
+            current: watch::Sender::new(0),
+        }
+    }
+
+    /// All endpoints in priority order
+    #[must_use]
+    pub fn endpoints(&self) -> &[ApiEndpoint] {
+        &self.endpoints
+    }
+
+    /// The endpoint currently used for regular requests
+    #[must_use]
+    pub fn current(&self) -> &ApiEndpoint {
+        &self.endpoints[*self.current.borrow()]
+    }
+
+    /// General-purpose bot of the current endpoint
+    pub fn bot(&self) -> Bot {
+        self.current().bot()
+    }
+
+    /// Receiver of the current endpoint index, marked changed on every failover
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<usize> {
+        self.current.subscribe()
+    }
+
+    /// Largest file in bytes that one of the upload candidates accepts
+    #[must_use]
+    pub fn upload_limit(&self) -> u64 {
+        self.upload_candidates()
+            .iter()
+            .map(|endpoint| endpoint.upload_limit)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Endpoints to try for an upload, best first. Endpoints whose breaker is open
+    /// are only included when nothing else is left.
+    #[must_use]
+    pub fn upload_candidates(&self) -> Vec<&ApiEndpoint> {
+        let current = *self.current.borrow();
+        let mut candidates: Vec<&ApiEndpoint> = std::iter::once(&self.endpoints[current])
+            .chain(
+                self.endpoints
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != current)
+                    .map(|(_, e)| e),
+            )
+            .filter(|e| e.is_available())
+            .collect();
+        if candidates.is_empty() {
+            candidates.push(&self.endpoints[current]);
+        }
+        candidates
+    }
+
+    /// Re-evaluate which endpoint should be current
+    fn select(&self) {
+        let selected = self
+            .endpoints
+            .iter()
+            .position(ApiEndpoint::is_available)
+            .unwrap_or(self.endpoints.len() - 1);
+        self.current.send_if_modified(|current| {
+            let previous = std::mem::replace(current, selected);
+            if previous != selected {
+                tracing::warn!(
+                    "Telegram API endpoint switched: {} -> {}",
+                    self.endpoints[previous].url,
+                    self.endpoints[selected].url
+                );
+            }
+            previous != selected
+        });
+    }
+
+    /// Record a failed request against an endpoint and fail over if needed
+    pub fn report_failure(&self, endpoint: &ApiEndpoint, error: &RequestError) {
+        // Only transport-level errors say something about the endpoint itself
+        if matches!(error, RequestError::Network(_) | RequestError::Io(_)) {
+            endpoint.record_failure(&error.to_string());
+            self.select();
+        }
+    }
+
+    /// Probe every endpoint once and pick the current one
+    pub async fn probe_all(&self) {
+        for endpoint in &self.endpoints {
+            // Endpoints with an open breaker are left alone until the cooldown passes
+            if endpoint.is_available() {
+                endpoint.probe().await;
+            }
+        }
+        self.select();
+    }
+
+    /// Run health probes in the background for the lifetime of the bot
+    pub fn spawn_health_checks(self: &Arc<Self>) {
+        let pool = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PROBE_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                pool.probe_all().await;
+            }
+        });
+    }
+
+    /// Short status text for `/status`
+    #[must_use]
+    pub fn status_text(&self) -> String {
+        let available = self.endpoints.iter().filter(|e| e.is_available()).count();
+        let current = self.current();
+        let host = current.url.host_str().unwrap_or("unknown");
+        format!(
+            "{host} ({}) [{available}/{} 可用]",
+            current.health_summary(),
+            self.endpoints.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_pool(apis: &[&str], local_mode: bool) -> EndpointPool {
+        let config = Config {
+            bot_api: apis.iter().map(ToString::to_string).collect(),
+            local_mode,
+            ..Config::default()
+        };
+        EndpointPool::new(&config)
+    }
+
+    fn fail(endpoint: &ApiEndpoint) {
+        for _ in 0..FAILURE_THRESHOLD {
+            endpoint.record_failure("connection refused");
+        }
+    }
+
+    fn hosts(endpoints: &[&ApiEndpoint]) -> Vec<String> {
+        endpoints
+            .iter()
+            .map(|e| e.url.host_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_official_api_is_last_resort() {
+        let pool = build_pool(
+            &["https://a.example.com", "https://b.example.com/bot"],
+            false,
+        );
+        let all: Vec<&ApiEndpoint> = pool.endpoints().iter().collect();
+        assert_eq!(
+            hosts(&all),
+            vec!["a.example.com", "b.example.com", "api.telegram.org"]
+        );
+        assert_eq!(pool.endpoints()[1].url.path(), "/bot");
+
+        // Listed explicitly, the official API keeps its place
+        let listed = build_pool(
+            &["https://api.telegram.org", "https://a.example.com"],
+            false,
+        );
+        assert_eq!(listed.endpoints().len(), 2);
+        assert_eq!(listed.current().url.host_str(), Some("api.telegram.org"));
+    }
+
+    #[test]
+    fn test_failure_threshold_and_recovery() {
+        let pool = build_pool(&["https://a.example.com"], false);
+        let endpoint = &pool.endpoints()[0];
+
+        for _ in 1..FAILURE_THRESHOLD {
+            endpoint.record_failure("timeout");
+        }
+        assert!(endpoint.is_available());
+        endpoint.record_failure("timeout");
+        assert!(!endpoint.is_available());
+        assert!(endpoint.health_summary().starts_with("熔断"));
+
+        // Half-open once the cooldown has passed, closed again by a success
+        endpoint.health.lock().unwrap().open_until = Some(Instant::now());
+        assert!(endpoint.is_available());
+        endpoint.record_success(Some(Duration::from_millis(20)));
+        assert!(endpoint.is_available());
+        assert_eq!(endpoint.health_summary(), "正常, 20ms");
+
+        // A success resets the count of failures in a row
+        endpoint.record_failure("timeout");
+        endpoint.record_success(None);
+        endpoint.record_failure("timeout");
+        assert!(endpoint.is_available());
+    }
+
+    #[test]
+    fn test_failover_order() {
+        let pool = build_pool(&["https://a.example.com", "https://b.example.com"], false);
+        let mut switches = pool.subscribe();
+        let endpoints = pool.endpoints();
+
+        fail(&endpoints[0]);
+        pool.select();
+        assert_eq!(pool.current().url.host_str(), Some("b.example.com"));
+        assert!(switches.has_changed().unwrap());
+        switches.borrow_and_update();
+        assert_eq!(
+            hosts(&pool.upload_candidates()),
+            vec!["b.example.com", "api.telegram.org"]
+        );
+
+        // With every breaker open the official API is used anyway
+        fail(&endpoints[1]);
+        fail(&endpoints[2]);
+        pool.select();
+        assert_eq!(pool.current().url.host_str(), Some("api.telegram.org"));
+        assert_eq!(hosts(&pool.upload_candidates()), vec!["api.telegram.org"]);
+
+        // The preferred endpoint takes over again once it recovers
+        endpoints[0].record_success(None);
+        pool.select();
+        assert_eq!(pool.current().url.host_str(), Some("a.example.com"));
+        assert!(switches.has_changed().unwrap());
+
+        // Selecting the same endpoint again is not a switch
+        switches.borrow_and_update();
+        pool.select();
+        assert!(!switches.has_changed().unwrap());
+    }
+
+    #[test]
+    fn test_upload_limit() {
+        let pool = build_pool(&["http://127.0.0.1:8081"], true);
+        let endpoints = pool.endpoints();
+        assert!(endpoints[0].local);
+        assert_eq!(
+            endpoints[0].upload_limit,
+            LOCAL_UPLOAD_LIMIT_MB * 1024 * 1024
+        );
+        assert_eq!(
+            endpoints[1].upload_limit,
+            OFFICIAL_UPLOAD_LIMIT_MB * 1024 * 1024
+        );
+        assert_eq!(pool.upload_limit(), LOCAL_UPLOAD_LIMIT_MB * 1024 * 1024);
+
+        // Only the official API is left to upload through
+        fail(&endpoints[0]);
+        pool.select();
+        assert_eq!(pool.upload_limit(), OFFICIAL_UPLOAD_LIMIT_MB * 1024 * 1024);
+    }
+}
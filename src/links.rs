@@ -0,0 +1,399 @@
+//! Typed parsing of NetEase Cloud Music links
+//!
+//! Understands web links (`music.163.com/#/song?id=1`, `music.163.com/song/1/`),
+//! mobile share links (`y.music.163.com/m/song?id=1`), app deep links
+//! (`orpheus://song/1`), links embedded in share text and bare song IDs.
+
+use regex::Regex;
+use teloxide::types::{Message, MessageEntityKind, MessageEntityRef};
+use url::Url;
+
+/// Candidate URLs inside arbitrary text
+static URL_REGEX: std::sync::LazyLock<Regex> = std::sync::LazyLock::new(|| {
+    Regex::new(
+        r#"(?i)(?:https?|orpheus)://[^\s<>"'，。、；！？《》（）【】]+|(?:[\w-]+\.)*music\.163\.com/[^\s<>"'，。、；！？《》（）【】]+"#,
+    )
+    .unwrap()
+});
+
+/// `163cn.tv` / `163cn.link` share short links, which redirect to `music.163.com`
+static SHORT_LINK_REGEX: std::sync::LazyLock<Regex> = std::sync::LazyLock::new(|| {
+    Regex::new(r"(?i)(?:https?://)?(?:163cn\.tv|163cn\.link)/[A-Za-z0-9]+").unwrap()
+});
+
+/// Find share short links in `text`, in order of appearance, normalized to `https://`
+#[must_use]
+pub fn find_short_links(text: &str) -> Vec<String> {
+    SHORT_LINK_REGEX
+        .find_iter(text)
+        .map(|m| normalize_short_link(m.as_str()))
+        .collect()
+}
+
+/// A link found in a message, before short links are resolved
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MessageLink {
+    Netease(NeteaseLink),
+    /// A `163cn.tv` / `163cn.link` short link that still has to be resolved
+    Short(String),
+}
+
+/// Collect all links from the text or caption of a message, including the targets of
+/// `text_link` entities, in order of appearance and without duplicates
+#[must_use]
+pub fn find_message_links(msg: &Message) -> Vec<MessageLink> {
+    if let (Some(text), Some(entities)) = (msg.text(), msg.parse_entities()) {
+        find_links(text, &entities)
+    } else if let (Some(caption), Some(entities)) = (msg.caption(), msg.parse_caption_entities()) {
+        find_links(caption, &entities)
+    } else {
+        find_links(
+            msg.text().or_else(|| msg.caption()).unwrap_or_default(),
+            &[],
+        )
+    }
+}
+
+/// Collect links from `text` and the URLs behind its `text_link` entities
+#[must_use]
+pub fn find_links(text: &str, entities: &[MessageEntityRef<'_>]) -> Vec<MessageLink> {
+    let mut found: Vec<(usize, MessageLink)> = Vec::new();
+    collect_links(text, 0, &mut found);
+    for entity in entities {
+        if let MessageEntityKind::TextLink { url } = entity.kind() {
+            collect_links(url.as_str(), entity.start(), &mut found);
+        }
+    }
+
+    found.sort_by_key(|(position, _)| *position);
+    let mut links: Vec<MessageLink> = Vec::new();
+    for (_, link) in found {
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+    links
+}
+
+/// Push every link in `text`, positioned relative to `base`
+fn collect_links(text: &str, base: usize, found: &mut Vec<(usize, MessageLink)>) {
+    for m in URL_REGEX.find_iter(text) {
+        if let Some(link) = NeteaseLink::parse_url(m.as_str()) {
+            found.push((base + m.start(), MessageLink::Netease(link)));
+        }
+    }
+    for m in SHORT_LINK_REGEX.find_iter(text) {
+        found.push((
+            base + m.start(),
+            MessageLink::Short(normalize_short_link(m.as_str())),
+        ));
+    }
+}
+
+fn normalize_short_link(link: &str) -> String {
+    let without_scheme = link.split_once("://").map_or(link, |(_, rest)| rest);
+    format!("https://{without_scheme}")
+}
+
+/// A NetEase entity referenced by a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NeteaseLink {
+    Song(u64),
+    Album(u64),
+    Playlist(u64),
+    Artist(u64),
+    Mv(u64),
+    DjProgram(u64),
+    User(u64),
+}
+
+impl NeteaseLink {
+    /// Parse the first NetEase link found in `text`.
+    /// A text consisting only of digits is treated as a song ID.
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let trimmed = text.trim();
+        if !trimmed.is_empty() && trimmed.bytes().all(|b| b.is_ascii_digit()) {
+            return trimmed.parse().ok().map(Self::Song);
+        }
+
+        Self::find_all(text).into_iter().next()
+    }
+
+    /// Parse every NetEase link in `text`, in order of appearance
+    #[must_use]
+    pub fn find_all(text: &str) -> Vec<Self> {
+        URL_REGEX
+            .find_iter(text)
+            .filter_map(|m| Self::parse_url(m.as_str()))
+            .collect()
+    }
+
+    /// Parse a single URL
+    #[must_use]
+    pub fn parse_url(raw: &str) -> Option<Self> {
+        let raw = raw.trim_end_matches(['.', ',', ')', ']', '!', '?']);
+        let with_scheme = if raw.contains("://") {
+            raw.to_string()
+        } else {
+            format!("https://{raw}")
+        };
+        let url = Url::parse(&with_scheme).ok()?;
+
+        if url.scheme().eq_ignore_ascii_case("orpheus") {
+            // orpheus://song/123 -> host "song", path "/123"
+            let kind = url.host_str()?;
+            let id = url
+                .path_segments()
+                .and_then(|mut segments| segments.find(|s| !s.is_empty()))
+                .and_then(|s| s.parse().ok())
+                .or_else(|| query_id(&url))?;
+            return Self::from_kind(kind, id);
+        }
+
+        let host = url.host_str()?.to_ascii_lowercase();
+        if host != "music.163.com" && !host.ends_with(".music.163.com") {
+            return None;
+        }
+
+        // Single page app links keep the route in the fragment: /#/song?id=1
+        let route = match url.fragment() {
+            Some(fragment) if fragment.starts_with('/') => {
+                Url::parse(&format!("https://music.163.com{fragment}")).ok()?
+            }
+            _ => url,
+        };
+
+        let segments: Vec<&str> = route
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+
+        for (i, segment) in segments.iter().enumerate() {
+            let kind = segment.to_ascii_lowercase();
+            if Self::from_kind(&kind, 0).is_none() {
+                continue;
+            }
+            // Prefer /song/123 over the query, fall back to ?id=123
+            let id = segments
+                .get(i + 1)
+                .and_then(|s| s.parse().ok())
+                .or_else(|| query_id(&route))?;
+            return Self::from_kind(&kind, id);
+        }
+
+        None
+    }
+
+    fn from_kind(kind: &str, id: u64) -> Option<Self> {
+        match kind.to_ascii_lowercase().as_str() {
+            "song" => Some(Self::Song(id)),
+            "album" => Some(Self::Album(id)),
+            "playlist" | "toplist" => Some(Self::Playlist(id)),
+            "artist" => Some(Self::Artist(id)),
+            "mv" => Some(Self::Mv(id)),
+            "program" | "dj" => Some(Self::DjProgram(id)),
+            "user" => Some(Self::User(id)),
+            _ => None,
+        }
+    }
+
+    /// Canonical web URL of the entity
+    #[must_use]
+    pub fn url(&self) -> String {
+        let (path, id) = match self {
+            Self::Song(id) => ("song", id),
+            Self::Album(id) => ("album", id),
+            Self::Playlist(id) => ("playlist", id),
+            Self::Artist(id) => ("artist", id),
+            Self::Mv(id) => ("mv", id),
+            Self::DjProgram(id) => ("program", id),
+            Self::User(id) => ("user/home", id),
+        };
+        format!("https://music.163.com/{path}?id={id}")
+    }
+
+    /// Chinese name of the entity type, for user facing messages
+    #[must_use]
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Self::Song(_) => "歌曲",
+            Self::Album(_) => "专辑",
+            Self::Playlist(_) => "歌单",
+            Self::Artist(_) => "歌手",
+            Self::Mv(_) => "MV",
+            Self::DjProgram(_) => "电台节目",
+            Self::User(_) => "用户",
+        }
+    }
+}
+
+/// Extract the numeric `id` query parameter
+fn query_id(url: &Url) -> Option<u64> {
+    url.query_pairs()
+        .find(|(key, _)| key == "id")
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_table() {
+        let cases: &[(&str, Option<NeteaseLink>)] = &[
+            // Plain IDs
+            ("1234567", Some(NeteaseLink::Song(1_234_567))),
+            ("  1234567 ", Some(NeteaseLink::Song(1_234_567))),
+            // Web links
+            (
+                "https://music.163.com/song?id=1234567",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "https://music.163.com/#/song?id=1234567",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "http://music.163.com/song/1234567/",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "https://music.163.com/song/1234567/?userid=42",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "music.163.com/song?id=1234567",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "https://music.163.com/song?userid=42&id=1234567",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            // Mobile share links
+            (
+                "https://y.music.163.com/m/song?id=1234567&uct2=abc",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "https://y.music.163.com/m/song/1234567/",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "https://y.music.163.com/m/playlist?id=19723756",
+                Some(NeteaseLink::Playlist(19_723_756)),
+            ),
+            // Other entity types
+            (
+                "https://music.163.com/#/album?id=34720827",
+                Some(NeteaseLink::Album(34_720_827)),
+            ),
+            (
+                "https://music.163.com/album/34720827/",
+                Some(NeteaseLink::Album(34_720_827)),
+            ),
+            (
+                "https://music.163.com/#/playlist?id=2829816518",
+                Some(NeteaseLink::Playlist(2_829_816_518)),
+            ),
+            (
+                "https://music.163.com/#/my/m/music/playlist?id=2829816518",
+                Some(NeteaseLink::Playlist(2_829_816_518)),
+            ),
+            (
+                "https://music.163.com/#/discover/toplist?id=3778678",
+                Some(NeteaseLink::Playlist(3_778_678)),
+            ),
+            (
+                "https://music.163.com/#/artist?id=6452",
+                Some(NeteaseLink::Artist(6452)),
+            ),
+            (
+                "https://music.163.com/#/mv?id=5436712",
+                Some(NeteaseLink::Mv(5_436_712)),
+            ),
+            (
+                "https://music.163.com/#/program?id=2061034798",
+                Some(NeteaseLink::DjProgram(2_061_034_798)),
+            ),
+            (
+                "https://music.163.com/dj?id=2061034798",
+                Some(NeteaseLink::DjProgram(2_061_034_798)),
+            ),
+            (
+                "https://music.163.com/#/user/home?id=1463586082",
+                Some(NeteaseLink::User(1_463_586_082)),
+            ),
+            // App deep links
+            ("orpheus://song/1234567", Some(NeteaseLink::Song(1_234_567))),
+            (
+                "orpheus://playlist/2829816518",
+                Some(NeteaseLink::Playlist(2_829_816_518)),
+            ),
+            ("orpheus://album/34720827", Some(NeteaseLink::Album(34_720_827))),
+            // Share text
+            (
+                "分享Taylor Swift的单曲《Love Story》: https://y.music.163.com/m/song?id=1234567&userid=42 (来自@网易云音乐)",
+                Some(NeteaseLink::Song(1_234_567)),
+            ),
+            (
+                "分享歌单《华语经典》https://music.163.com/playlist?id=2829816518&userid=42，快来听吧",
+                Some(NeteaseLink::Playlist(2_829_816_518)),
+            ),
+            // Not NetEase links
+            ("https://example.com/song?id=1234567", None),
+            ("https://music.163.com/song", None),
+            ("https://music.163.com/#/discover", None),
+            ("hello world", None),
+            ("12a34", None),
+            ("", None),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(NeteaseLink::parse(input), *expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_find_all_keeps_order() {
+        let text = "https://music.163.com/song?id=1 和 orpheus://album/2 还有 https://example.com/song?id=3";
+        assert_eq!(
+            NeteaseLink::find_all(text),
+            vec![NeteaseLink::Song(1), NeteaseLink::Album(2)]
+        );
+    }
+
+    #[test]
+    fn test_find_short_links() {
+        let text = "分享单曲 http://163cn.tv/abc123 (来自@网易云音乐) 163cn.link/XyZ9";
+        assert_eq!(
+            find_short_links(text),
+            vec!["https://163cn.tv/abc123", "https://163cn.link/XyZ9"]
+        );
+        assert!(find_short_links("https://music.163.com/song?id=1").is_empty());
+    }
+
+    #[test]
+    fn test_find_links_with_text_link_entities() {
+        use teloxide::types::MessageEntity;
+
+        let text =
+            "听 这首 和 music.163.com/song?id=1 还有 163cn.tv/abc 以及 music.163.com/song?id=1";
+        // "这首" is a text link to another song (UTF-16 offset 2, length 2)
+        let entities = vec![MessageEntity::text_link(
+            reqwest::Url::parse("https://music.163.com/#/song?id=2").unwrap(),
+            2,
+            2,
+        )];
+        let entities = MessageEntityRef::parse(text, &entities);
+
+        assert_eq!(
+            find_links(text, &entities),
+            vec![
+                MessageLink::Netease(NeteaseLink::Song(2)),
+                MessageLink::Netease(NeteaseLink::Song(1)),
+                MessageLink::Short("https://163cn.tv/abc".to_string()),
+            ]
+        );
+    }
+}
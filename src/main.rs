@@ -11,15 +11,26 @@
     clippy::struct_excessive_bools,
     clippy::doc_markdown,
     clippy::needless_pass_by_value,
-    clippy::format_push_string
+    clippy::format_push_string,
+    clippy::map_unwrap_or,
+    clippy::derivable_impls
 )]
 
+pub mod account;
 pub mod audio_buffer;
 pub mod bot;
+pub mod cache;
 pub mod config;
+pub mod credentials;
+pub mod crypto;
 pub mod database;
+pub mod device;
+pub mod endpoints;
 pub mod error;
+pub mod links;
 pub mod music_api;
+pub mod ranking;
+pub mod send_queue;
 pub mod utils;
 
 use anyhow::Result;
@@ -1,23 +1,94 @@
+use crate::account::{is_account_rejection, Account, AccountPool};
+use crate::cache::TtlCache;
+use crate::crypto;
+use crate::device::{anonymous_username, ClientProfile, DeviceIdentity};
 use crate::error::{BotError, Result};
-use aes::Aes128;
-use cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyInit};
-use ecb::{Decryptor, Encryptor};
-use hex::encode_upper;
+use crate::links::NeteaseLink;
 use image::{DynamicImage, GenericImageView, ImageFormat};
-use md5::compute as md5_compute;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::future::Future;
 use std::io::Cursor;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
-use uuid::Uuid;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::watch;
+
+/// Maximum number of redirects followed when resolving a short link
+const MAX_REDIRECTS: usize = 10;
+/// Resolved short links kept in memory
+const SHORT_LINK_CACHE_SIZE: usize = 1024;
+/// How long a resolved short link is trusted
+const SHORT_LINK_TTL: Duration = Duration::from_hours(24);
+/// Minimum time between anonymous registration attempts of an unregistered device
+const REGISTRATION_RETRY_INTERVAL: Duration = Duration::from_mins(10);
+
+/// User agent of linuxapi requests
+const LINUXAPI_USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/60.0.3112.90 Safari/537.36";
+
+/// How a request reaches NetEase, see `crypto`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// Plain form POST to `/api/...`
+    Api,
+    /// Web client encryption, `/weapi/...`
+    Weapi,
+    /// Mobile client encryption, `/eapi/...`
+    Eapi,
+    /// Linux client encryption through `/api/linux/forward`
+    Linuxapi,
+}
+
+/// Form fields of a plain api request: strings as they are, other values as JSON
+fn form_fields(payload: &serde_json::Value) -> Vec<(String, String)> {
+    payload
+        .as_object()
+        .map(|fields| {
+            fields
+                .iter()
+                .map(|(name, value)| {
+                    let value = value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), str::to_string);
+                    (name.clone(), value)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Bitrates requested from NetEase, from best to worst
+pub const QUALITY_LEVELS: &[u64] = &[999_000, 320_000, 192_000, 128_000];
+
+/// Human readable label for a requested bitrate
+#[must_use]
+pub fn quality_label(br: u64) -> &'static str {
+    match br {
+        br if br >= 999_000 => "无损 FLAC",
+        br if br >= 320_000 => "320k MP3",
+        br if br >= 192_000 => "192k MP3",
+        _ => "128k MP3",
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct MusicApi {
     client: Client,
-    pub music_u: Option<String>,
+    /// Client that does not follow redirects, used to resolve short links
+    redirect_client: Client,
+    /// Login cookies requests are spread over
+    accounts: Arc<AccountPool>,
+    /// Account of the requesting user, tried before the pool
+    user_account: Option<Arc<Account>>,
+    /// App version, OS and user agent of eapi requests
+    profile: Arc<ClientProfile>,
+    /// Device all eapi requests share
+    device: Arc<watch::Sender<DeviceIdentity>>,
+    /// Time of the last anonymous registration attempt, locked while one is running
+    registration: Arc<tokio::sync::Mutex<Option<Instant>>>,
     base_url: String,
+    short_links: Arc<TtlCache<String, NeteaseLink>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,6 +107,98 @@ pub struct SongDetail {
     pub ar: Option<Vec<Artist>>, // Artists array (may be missing)
     #[serde(alias = "album")]
     pub al: Option<Album>, // Album info (may be missing)
+    /// Aliases, e.g. the film a song was written for
+    #[serde(default, alias = "alias")]
+    pub alia: Vec<String>,
+    /// Translated names
+    #[serde(default, alias = "transNames")]
+    pub tns: Option<Vec<String>>,
+    /// Popularity from 0 to 100
+    #[serde(default, alias = "popularity")]
+    pub pop: Option<f64>,
+    /// Publish time in milliseconds
+    #[serde(default, rename = "publishTime")]
+    pub publish_time: Option<i64>,
+    /// 0/8: free, 1: VIP only, 4: paid album
+    #[serde(default)]
+    pub fee: Option<i64>,
+    /// Hi-Res, lossless, high, medium and low quality files
+    #[serde(default)]
+    pub hr: Option<SongQuality>,
+    #[serde(default)]
+    pub sq: Option<SongQuality>,
+    #[serde(default, alias = "hMusic")]
+    pub h: Option<SongQuality>,
+    #[serde(default, alias = "mMusic")]
+    pub m: Option<SongQuality>,
+    #[serde(default, alias = "lMusic")]
+    pub l: Option<SongQuality>,
+}
+
+impl SongDetail {
+    /// Available files as `(level, size)`, best first, where level is one of `QUALITY_LEVELS`
+    #[must_use]
+    pub fn qualities(&self) -> Vec<(u64, u64)> {
+        let lossless = self
+            .sq
+            .as_ref()
+            .or(self.hr.as_ref())
+            .map(|q| (QUALITY_LEVELS[0], q.size));
+        let lossy = [
+            (QUALITY_LEVELS[1], &self.h),
+            (QUALITY_LEVELS[2], &self.m),
+            (QUALITY_LEVELS[3], &self.l),
+        ];
+        lossless
+            .into_iter()
+            .chain(
+                lossy
+                    .into_iter()
+                    .filter_map(|(level, q)| q.as_ref().map(|q| (level, q.size))),
+            )
+            .filter(|(_, size)| *size > 0)
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SongQuality {
+    #[serde(default, alias = "bitrate")]
+    pub br: u64,
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// What the current account may do with a song
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Privilege {
+    pub id: u64,
+    #[serde(default)]
+    pub fee: i64,
+    /// Negative when the song has been taken down
+    #[serde(default)]
+    pub st: i64,
+    /// Highest bitrate that can be played
+    #[serde(default)]
+    pub pl: u64,
+    /// Highest bitrate that can be downloaded
+    #[serde(default)]
+    pub dl: u64,
+    /// Highest bitrate that exists
+    #[serde(default)]
+    pub maxbr: u64,
+    /// Set when only a preview may be played
+    #[serde(rename = "freeTrialInfo", default)]
+    pub free_trial_info: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SongDetailV3Response {
+    code: i32,
+    #[serde(default)]
+    songs: Vec<SongDetail>,
+    #[serde(default)]
+    privileges: Vec<Privilege>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -69,154 +232,1118 @@ pub struct SongUrl {
     pub format: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LyricResponse {
-    pub code: i32,
-    pub lrc: Option<LyricContent>,
-    pub tlyric: Option<LyricContent>,
-}
+impl SongUrl {
+    /// The `QUALITY_LEVELS` entry served for a request at level `requested`. Lossless
+    /// files report their actual bitrate, often well below 999k, and NetEase falls back
+    /// to a lower level when the requested one is not available.
+    #[must_use]
+    pub fn level(&self, requested: u64) -> u64 {
+        let served = if self.format.eq_ignore_ascii_case("flac") {
+            QUALITY_LEVELS[0]
+        } else {
+            QUALITY_LEVELS[1..]
+                .iter()
+                .copied()
+                .find(|&level| self.br >= level)
+                .unwrap_or(QUALITY_LEVELS[QUALITY_LEVELS.len() - 1])
+        };
+        served.min(requested)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LyricResponse {
+    pub code: i32,
+    pub lrc: Option<LyricContent>,
+    pub tlyric: Option<LyricContent>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LyricContent {
+    pub lyric: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DjProgramResponse {
+    pub code: i32,
+    pub program: Option<DjProgram>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DjProgram {
+    pub id: u64,
+    pub name: String,
+    #[serde(rename = "mainSong")]
+    pub main_song: Option<SongDetail>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub code: i32,
+    pub result: SearchResult,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EapiSearchResponse {
+    pub code: i32,
+    #[serde(default)]
+    pub result: SearchResult,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HotSearchResponse {
+    pub code: i32,
+    pub result: Option<HotSearchResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HotSearchResult {
+    #[serde(default)]
+    pub hots: Vec<HotSearch>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HotSearch {
+    pub first: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SuggestResponse {
+    pub code: i32,
+    pub result: Option<SuggestResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SuggestResult {
+    #[serde(rename = "allMatch", default)]
+    pub all_match: Vec<SuggestKeyword>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SuggestKeyword {
+    pub keyword: String,
+}
+
+/// NetEase search types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SearchType {
+    Song,
+    Album,
+    Artist,
+    Playlist,
+    User,
+    Mv,
+    /// Find songs by a line of their lyrics
+    Lyric,
+}
+
+impl SearchType {
+    /// `type` parameter of the search API
+    #[must_use]
+    pub fn code(self) -> u32 {
+        match self {
+            Self::Song => 1,
+            Self::Album => 10,
+            Self::Artist => 100,
+            Self::Playlist => 1000,
+            Self::User => 1002,
+            Self::Mv => 1004,
+            Self::Lyric => 1006,
+        }
+    }
+
+    /// Chinese name of the search type, for user facing messages
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Song => "歌曲",
+            Self::Album => "专辑",
+            Self::Artist => "歌手",
+            Self::Playlist => "歌单",
+            Self::User => "用户",
+            Self::Mv => "MV",
+            Self::Lyric => "歌词",
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix.to_ascii_lowercase().as_str() {
+            "song" | "歌曲" => Some(Self::Song),
+            "album" | "专辑" => Some(Self::Album),
+            "artist" | "歌手" => Some(Self::Artist),
+            "playlist" | "歌单" => Some(Self::Playlist),
+            "user" | "用户" => Some(Self::User),
+            "mv" => Some(Self::Mv),
+            "lyric" | "歌词" => Some(Self::Lyric),
+            _ => None,
+        }
+    }
+
+    /// Split a query like `album: 范特西` into its search type and keyword.
+    /// Queries without a known prefix are song searches.
+    #[must_use]
+    pub fn parse_query(query: &str) -> (Self, &str) {
+        let query = query.trim();
+        if let Some((prefix, keyword)) = query.split_once([':', '：']) {
+            if let Some(search_type) = Self::from_prefix(prefix.trim()) {
+                return (search_type, keyword.trim());
+            }
+        }
+        (Self::Song, query)
+    }
+}
+
+/// Search results; only the list matching the search type is filled
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchResult {
+    #[serde(default)]
+    pub songs: Vec<SearchSong>,
+    /// Total number of matches, not just this page
+    #[serde(rename = "songCount", default)]
+    pub song_count: u64,
+    #[serde(default)]
+    pub albums: Vec<SearchAlbum>,
+    #[serde(rename = "albumCount", default)]
+    pub album_count: u64,
+    #[serde(default)]
+    pub artists: Vec<SearchArtist>,
+    #[serde(rename = "artistCount", default)]
+    pub artist_count: u64,
+    #[serde(default)]
+    pub playlists: Vec<SearchPlaylist>,
+    #[serde(rename = "playlistCount", default)]
+    pub playlist_count: u64,
+    #[serde(default)]
+    pub userprofiles: Vec<SearchUser>,
+    #[serde(rename = "userprofileCount", default)]
+    pub userprofile_count: u64,
+    #[serde(default)]
+    pub mvs: Vec<SearchMv>,
+    #[serde(rename = "mvCount", default)]
+    pub mv_count: u64,
+}
+
+impl SearchResult {
+    /// Total number of matches for `search_type`
+    #[must_use]
+    pub fn total(&self, search_type: SearchType) -> u64 {
+        match search_type {
+            SearchType::Song | SearchType::Lyric => self.song_count,
+            SearchType::Album => self.album_count,
+            SearchType::Artist => self.artist_count,
+            SearchType::Playlist => self.playlist_count,
+            SearchType::User => self.userprofile_count,
+            SearchType::Mv => self.mv_count,
+        }
+    }
+
+    /// Number of results on this page for `search_type`
+    #[must_use]
+    pub fn len(&self, search_type: SearchType) -> usize {
+        match search_type {
+            SearchType::Song | SearchType::Lyric => self.songs.len(),
+            SearchType::Album => self.albums.len(),
+            SearchType::Artist => self.artists.len(),
+            SearchType::Playlist => self.playlists.len(),
+            SearchType::User => self.userprofiles.len(),
+            SearchType::Mv => self.mvs.len(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchSong {
+    pub id: u64,
+    pub name: String,
+    #[serde(alias = "ar")]
+    pub artists: Vec<Artist>,
+    #[serde(alias = "al")]
+    pub album: Album,
+    #[serde(alias = "dt")]
+    pub duration: u64,
+    /// 0: free, 1: VIP only, 4: paid album, 8: free at standard quality
+    #[serde(default)]
+    pub fee: i64,
+    /// What the bot's account may do with the song
+    #[serde(default)]
+    pub privilege: Option<Privilege>,
+    /// Matching lyrics of lyric searches, either a list of lines or `{"txt": ...}`
+    #[serde(default)]
+    pub lyrics: Option<serde_json::Value>,
+}
+
+impl SearchSong {
+    /// Short marker for songs that need VIP or a purchase, or that the bot's account
+    /// can only play a preview of
+    #[must_use]
+    pub fn fee_label(&self) -> Option<&'static str> {
+        if let Some(privilege) = &self.privilege {
+            if privilege.st < 0 {
+                return Some("无版权");
+            }
+            let restricted = privilege.pl == 0 && matches!(self.fee, 1 | 4);
+            if privilege.free_trial_info.is_some() || restricted {
+                return Some("试听");
+            }
+        }
+        match self.fee {
+            1 => Some("VIP"),
+            4 => Some("付费"),
+            8 => Some("高音质VIP"),
+            _ => None,
+        }
+    }
+
+    /// The lyric line of a lyric search that best matches `keyword`
+    #[must_use]
+    pub fn lyric_snippet(&self, keyword: &str) -> Option<String> {
+        let lines: Vec<String> = match self.lyrics.as_ref()? {
+            serde_json::Value::Array(lines) => lines
+                .iter()
+                .filter_map(|line| line.as_str().map(str::to_string))
+                .collect(),
+            serde_json::Value::Object(lyrics) => lyrics
+                .get("txt")
+                .and_then(|txt| txt.as_str())
+                .map(|txt| txt.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+            _ => return None,
+        };
+
+        let keyword = keyword.to_lowercase();
+        let lines: Vec<&str> = lines
+            .iter()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+        lines
+            .iter()
+            .find(|line| line.to_lowercase().contains(&keyword))
+            .or_else(|| lines.first())
+            .map(|line| line.replace("<b>", "").replace("</b>", ""))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchAlbum {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub artists: Vec<Artist>,
+    /// Number of tracks
+    #[serde(default)]
+    pub size: u64,
+    #[serde(rename = "picUrl")]
+    pub pic_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchArtist {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub alias: Vec<String>,
+    #[serde(rename = "albumSize", default)]
+    pub album_size: u64,
+    #[serde(rename = "picUrl")]
+    pub pic_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchPlaylist {
+    pub id: u64,
+    pub name: String,
+    #[serde(rename = "trackCount", default)]
+    pub track_count: u64,
+    #[serde(rename = "playCount", default)]
+    pub play_count: u64,
+    pub creator: Option<PlaylistCreator>,
+    #[serde(rename = "coverImgUrl")]
+    pub cover_img_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistCreator {
+    #[serde(rename = "userId", default)]
+    pub user_id: u64,
+    pub nickname: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchUser {
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+    pub nickname: String,
+    pub signature: Option<String>,
+    #[serde(rename = "avatarUrl")]
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMv {
+    pub id: u64,
+    pub name: String,
+    #[serde(rename = "artistName", default)]
+    pub artist_name: String,
+    /// Duration in milliseconds
+    #[serde(default)]
+    pub duration: u64,
+    pub cover: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Comment {
+    #[serde(rename = "commentId")]
+    pub comment_id: u64,
+    pub user: CommentUser,
+    pub content: String,
+    #[serde(default, rename = "likedCount")]
+    pub liked_count: u64,
+    /// Posting time in milliseconds
+    #[serde(default)]
+    pub time: i64,
+    /// The comment this one replies to
+    #[serde(default, rename = "beReplied")]
+    pub be_replied: Option<Vec<RepliedComment>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommentUser {
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+    pub nickname: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RepliedComment {
+    pub user: CommentUser,
+    /// Missing when the replied comment was deleted
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CommentsResponse {
+    code: i32,
+    #[serde(default, rename = "hotComments")]
+    hot_comments: Vec<Comment>,
+    #[serde(default)]
+    comments: Vec<Comment>,
+    #[serde(default)]
+    total: u64,
+    /// `hasMore` for hot comments, `more` for latest comments
+    #[serde(default, alias = "hasMore")]
+    more: bool,
+}
+
+/// Which comments of a song to list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentSort {
+    Hot,
+    Latest,
+}
+
+impl CommentSort {
+    /// Short code used in callback data
+    #[must_use]
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Hot => "hot",
+            Self::Latest => "new",
+        }
+    }
+
+    #[must_use]
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "hot" => Some(Self::Hot),
+            "new" => Some(Self::Latest),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Hot => "热门评论",
+            Self::Latest => "最新评论",
+        }
+    }
+}
+
+/// One page of comments
+#[derive(Debug)]
+pub struct CommentPage {
+    pub comments: Vec<Comment>,
+    pub total: u64,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SimilarSongsResponse {
+    code: i32,
+    #[serde(default)]
+    songs: Vec<SearchSong>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlbumDetailResponse {
+    pub code: i32,
+    pub album: AlbumInfo,
+    #[serde(default)]
+    pub songs: Vec<SongDetail>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlbumInfo {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub artists: Vec<Artist>,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(rename = "picUrl")]
+    pub pic_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArtistDetailResponse {
+    pub code: i32,
+    pub artist: Artist,
+    #[serde(rename = "hotSongs", default)]
+    pub hot_songs: Vec<SongDetail>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistDetailResponse {
+    pub code: i32,
+    pub playlist: Option<Playlist>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Playlist {
+    pub id: u64,
+    pub name: String,
+    #[serde(rename = "trackCount", default)]
+    pub track_count: u64,
+    pub creator: Option<PlaylistCreator>,
+    #[serde(default)]
+    pub tracks: Vec<SongDetail>,
+}
+
+/// Progress of a QR code login
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QrLoginStatus {
+    /// Waiting for the code to be scanned
+    Waiting,
+    /// Scanned, waiting for the login to be confirmed in the app
+    Scanned {
+        nickname: Option<String>,
+    },
+    Expired,
+    /// Confirmed, with the new `MUSIC_U` cookie
+    Confirmed {
+        music_u: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct QrKeyResponse {
+    code: i32,
+    #[serde(default)]
+    unikey: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct QrCheckResponse {
+    code: i32,
+    #[serde(default)]
+    nickname: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+/// The NetEase user behind the login cookie
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountProfile {
+    #[serde(rename = "userId")]
+    pub user_id: u64,
+    pub nickname: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountResponse {
+    code: i32,
+    /// `null` when the cookie is no longer logged in
+    #[serde(default)]
+    profile: Option<AccountProfile>,
+}
+
+/// VIP membership of the logged in account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VipInfo {
+    /// 黑胶 VIP level, 0 without membership
+    pub level: i64,
+    /// Latest expiry of any active membership in milliseconds
+    pub expire_time: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VipInfoResponse {
+    code: i32,
+    #[serde(default)]
+    data: Option<VipInfoData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VipInfoData {
+    #[serde(default, rename = "redVipLevel")]
+    red_vip_level: i64,
+    #[serde(default)]
+    associator: Option<VipPackage>,
+    #[serde(default, rename = "musicPackage")]
+    music_package: Option<VipPackage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VipPackage {
+    #[serde(default, rename = "expireTime")]
+    expire_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CodeResponse {
+    code: i32,
+}
+
+/// URL encoded in the login QR code for `key`
+#[must_use]
+pub fn qr_login_url(key: &str) -> String {
+    format!("https://music.163.com/login?codekey={key}")
+}
+
+/// A chart such as 飙升榜; its tracks are a regular playlist
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Toplist {
+    pub id: u64,
+    pub name: String,
+    #[serde(default, rename = "updateFrequency")]
+    pub update_frequency: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ToplistResponse {
+    code: i32,
+    #[serde(default)]
+    list: Vec<Toplist>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewAlbumsResponse {
+    pub code: i32,
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub albums: Vec<SearchAlbum>,
+}
+
+impl MusicApi {
+    #[must_use]
+    pub fn new(cookies: &[String], base_url: String, profile: ClientProfile) -> Self {
+        let mut client_builder = Client::builder();
+
+        // Use rustls TLS for better compatibility
+        client_builder = client_builder.use_rustls_tls();
+
+        // Performance optimizations
+        client_builder = client_builder
+            .tcp_nodelay(true)
+            .pool_idle_timeout(std::time::Duration::from_secs(90))
+            .pool_max_idle_per_host(32)
+            .connect_timeout(std::time::Duration::from_secs(10));
+
+        // Add user agent
+        client_builder = client_builder
+            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36");
+
+        let client = client_builder.build().unwrap();
+
+        let redirect_client = Client::builder()
+            .use_rustls_tls()
+            .redirect(reqwest::redirect::Policy::none())
+            .connect_timeout(std::time::Duration::from_secs(10))
+            .timeout(std::time::Duration::from_secs(15))
+            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36")
+            .build()
+            .unwrap();
+
+        Self {
+            client,
+            redirect_client,
+            accounts: Arc::new(AccountPool::new(cookies)),
+            user_account: None,
+            profile: Arc::new(profile),
+            device: Arc::new(watch::Sender::new(DeviceIdentity::generate())),
+            registration: Arc::new(tokio::sync::Mutex::new(None)),
+            base_url,
+            short_links: Arc::new(TtlCache::new(SHORT_LINK_TTL, SHORT_LINK_CACHE_SIZE)),
+        }
+    }
+
+    /// Login cookies used for requests
+    #[must_use]
+    pub fn accounts(&self) -> &AccountPool {
+        &self.accounts
+    }
+
+    /// Client that sends requests with a user's own cookie first and falls back to the
+    /// pool when NetEase rejects it
+    #[must_use]
+    pub fn for_user(&self, music_u: &str) -> Self {
+        Self {
+            user_account: Some(Arc::new(Account::new(music_u))),
+            ..self.clone()
+        }
+    }
+
+    /// Whether NetEase rejected the user's own cookie as logged out
+    #[must_use]
+    pub fn user_logged_out(&self) -> bool {
+        self.user_account
+            .as_ref()
+            .is_some_and(|account| account.logged_out())
+    }
+
+    /// Whether requests may go out logged in
+    #[must_use]
+    pub fn has_login(&self) -> bool {
+        self.user_account.is_some() || self.accounts.has_login()
+    }
+
+    /// Switch to the device stored in the database
+    pub fn set_device(&self, device: DeviceIdentity) {
+        self.device.send_replace(device);
+    }
+
+    /// Changes of the device, such as a late anonymous registration
+    #[must_use]
+    pub fn subscribe_device(&self) -> watch::Receiver<DeviceIdentity> {
+        self.device.subscribe()
+    }
+
+    /// Register the device anonymously if it has no token yet, at most once per
+    /// `REGISTRATION_RETRY_INTERVAL`
+    async fn ensure_registered(&self) {
+        if self.device.borrow().music_a.is_some() {
+            return;
+        }
+        // Another request is registering right now
+        let Ok(mut last_attempt) = self.registration.try_lock() else {
+            return;
+        };
+        if last_attempt.is_some_and(|at| at.elapsed() < REGISTRATION_RETRY_INTERVAL) {
+            return;
+        }
+        *last_attempt = Some(Instant::now());
+
+        let device_id = self.device.borrow().device_id.clone();
+        match self.register_anonymous(&device_id).await {
+            Ok(music_a) => {
+                self.device.send_if_modified(|device| {
+                    let registered = device.device_id == device_id;
+                    if registered {
+                        device.music_a = Some(music_a);
+                    }
+                    registered
+                });
+                tracing::info!("Registered NetEase device {}", device_id);
+            }
+            Err(e) => tracing::warn!("Failed to register NetEase device anonymously: {}", e),
+        }
+    }
+
+    fn build_eapi_cookie(&self, music_u: Option<&str>) -> String {
+        let device = self.device.borrow();
+        let buildver = SystemTime::now().duration_since(UNIX_EPOCH).map_or_else(
+            |_| "0".to_string(),
+            |duration| duration.as_secs().to_string(),
+        );
+        let mut cookie_parts = vec![
+            format!("deviceId={}", device.device_id),
+            format!("appver={}", self.profile.appver),
+            format!("buildver={}", &buildver[..buildver.len().min(10)]),
+            "resolution=1920x1080".to_string(),
+            format!("os={}", self.profile.os),
+        ];
+
+        if let Some(music_u) = music_u {
+            cookie_parts.push(format!("MUSIC_U={music_u}"));
+        } else if let Some(music_a) = &device.music_a {
+            cookie_parts.push(format!("MUSIC_A={music_a}"));
+        }
+
+        cookie_parts.join("; ")
+    }
+
+    /// Get song details
+    pub async fn get_song_detail(&self, song_id: u64) -> Result<SongDetail> {
+        let payload = serde_json::json!({
+            "id": song_id.to_string(),
+            "ids": format!("[{song_id}]"),
+        });
+        let data: SongDetailResponse = self
+            .call(Transport::Api, "/api/song/detail", &payload)
+            .await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        data.songs
+            .into_iter()
+            .next()
+            .ok_or_else(|| BotError::MusicApi("No song found".to_string()))
+    }
+
+    /// Get full song details with file sizes and the privileges of the current account
+    pub async fn get_song_info(&self, song_id: u64) -> Result<(SongDetail, Option<Privilege>)> {
+        let payload = serde_json::json!({ "c": format!("[{{\"id\":{song_id}}}]") });
+        let data: SongDetailV3Response = self
+            .call(Transport::Api, "/api/v3/song/detail", &payload)
+            .await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        let privilege = data.privileges.into_iter().find(|p| p.id == song_id);
+        let song = data
+            .songs
+            .into_iter()
+            .next()
+            .ok_or_else(|| BotError::MusicApi("No song found".to_string()))?;
+        Ok((song, privilege))
+    }
+
+    /// Get song download URL
+    pub async fn get_song_url(&self, song_id: u64, br: u64) -> Result<SongUrl> {
+        let payload = serde_json::json!({
+            "ids": format!("[{song_id}]"),
+            "br": br,
+        });
+        let data: SongUrlResponse = self
+            .call(Transport::Api, "/api/song/enhance/player/url", &payload)
+            .await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        data.data
+            .into_iter()
+            .next()
+            .ok_or_else(|| BotError::MusicApi("No download URL found".to_string()))
+    }
+
+    /// Get song lyrics
+    pub async fn get_song_lyric(&self, song_id: u64) -> Result<String> {
+        let url = format!("{}/api/song/lyric?id={}&lv=1&tv=1", self.base_url, song_id);
+
+        let data: LyricResponse = self.get_json(&url).await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        let lyric = data
+            .lrc
+            .map_or_else(|| "No lyrics available".to_string(), |l| l.lyric);
+
+        Ok(lyric)
+    }
+
+    /// Get the song behind a DJ program
+    pub async fn get_dj_program_song_id(&self, program_id: u64) -> Result<u64> {
+        let url = format!("{}/api/dj/program/detail?id={}", self.base_url, program_id);
+
+        let data: DjProgramResponse = self.get_json(&url).await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        data.program
+            .and_then(|program| program.main_song)
+            .map(|song| song.id)
+            .ok_or_else(|| BotError::MusicApi("No song found in program".to_string()))
+    }
+
+    /// Search songs, returning `limit` results starting at `offset`
+    pub async fn search_songs(
+        &self,
+        keyword: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<SearchResult> {
+        let payload = serde_json::json!({
+            "s": keyword,
+            "offset": offset,
+            "limit": limit.max(1),
+        });
+        let data: EapiSearchResponse = self
+            .call(Transport::Eapi, "/api/v1/search/song/get", &payload)
+            .await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        Ok(data.result)
+    }
+
+    /// Search any entity type, returning `limit` results starting at `offset`.
+    /// Only the list matching `search_type` is filled in the result.
+    pub async fn search(
+        &self,
+        keyword: &str,
+        search_type: SearchType,
+        limit: u32,
+        offset: u32,
+    ) -> Result<SearchResult> {
+        if search_type == SearchType::Song {
+            return self.search_songs(keyword, limit, offset).await;
+        }
+
+        let payload = serde_json::json!({
+            "s": keyword,
+            "type": search_type.code(),
+            "offset": offset,
+            "limit": limit.max(1),
+            "total": true,
+        });
+        let data: EapiSearchResponse = self
+            .call(Transport::Eapi, "/api/cloudsearch/pc", &payload)
+            .await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct LyricContent {
-    pub lyric: String,
-}
+        Ok(data.result)
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SearchResponse {
-    pub code: i32,
-    pub result: SearchResult,
-}
+    /// Current hot search keywords
+    pub async fn get_hot_searches(&self) -> Result<Vec<String>> {
+        let url = format!("{}/api/search/hot?type=1111", self.base_url);
+        let data: HotSearchResponse = self.get_json(&url).await?;
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EapiSearchResponse {
-    pub code: i32,
-    pub result: SearchResult,
-}
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SearchResult {
-    pub songs: Vec<SearchSong>,
-    #[serde(rename = "songCount")]
-    pub song_count: u64,
-}
+        Ok(data
+            .result
+            .map(|result| result.hots.into_iter().map(|hot| hot.first).collect())
+            .unwrap_or_default())
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct SearchSong {
-    pub id: u64,
-    pub name: String,
-    pub artists: Vec<Artist>,
-    pub album: Album,
-    pub duration: u64,
-}
+    /// Keyword suggestions for partial input
+    pub async fn get_search_suggestions(&self, keyword: &str) -> Result<Vec<String>> {
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/api/search/suggest/keyword", self.base_url),
+            &[("s", keyword)],
+        )
+        .map_err(|e| BotError::Parse(format!("Invalid suggest URL: {e}")))?;
+        let data: SuggestResponse = self.get_json(url.as_str()).await?;
 
-impl MusicApi {
-    #[must_use]
-    pub fn new(music_u: Option<String>, base_url: String) -> Self {
-        let mut client_builder = Client::builder();
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
 
-        // Use rustls TLS for better compatibility
-        client_builder = client_builder.use_rustls_tls();
+        Ok(data
+            .result
+            .map(|result| {
+                result
+                    .all_match
+                    .into_iter()
+                    .map(|suggestion| suggestion.keyword)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
 
-        // Performance optimizations
-        client_builder = client_builder
-            .tcp_nodelay(true)
-            .pool_idle_timeout(std::time::Duration::from_secs(90))
-            .pool_max_idle_per_host(32)
-            .connect_timeout(std::time::Duration::from_secs(10));
+    /// Get an album with its tracks
+    pub async fn get_album(&self, album_id: u64) -> Result<AlbumDetailResponse> {
+        let url = format!("{}/api/v1/album/{}", self.base_url, album_id);
+        let data: AlbumDetailResponse = self.get_json(&url).await?;
 
-        // Add user agent
-        client_builder = client_builder
-            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/91.0.4472.124 Safari/537.36");
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
 
-        let client = client_builder.build().unwrap();
+        Ok(data)
+    }
 
-        Self {
-            client,
-            music_u,
-            base_url,
+    /// Get an artist with their hot songs
+    pub async fn get_artist(&self, artist_id: u64) -> Result<ArtistDetailResponse> {
+        let url = format!("{}/api/v1/artist/{}", self.base_url, artist_id);
+        let data: ArtistDetailResponse = self.get_json(&url).await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
         }
+
+        Ok(data)
     }
 
-    fn build_eapi_cookie(&self) -> String {
-        let device_id = Uuid::new_v4().simple().to_string();
-        let appver = "9.3.40";
-        let buildver = SystemTime::now().duration_since(UNIX_EPOCH).map_or_else(
-            |_| "0".to_string(),
-            |duration| duration.as_secs().to_string(),
+    /// Get a playlist with its tracks
+    pub async fn get_playlist(&self, playlist_id: u64) -> Result<Playlist> {
+        let url = format!(
+            "{}/api/v6/playlist/detail?id={}&n=1000",
+            self.base_url, playlist_id
         );
-        let mut cookie_parts = vec![
-            format!("deviceId={}", device_id),
-            format!("appver={}", appver),
-            format!("buildver={}", &buildver[..buildver.len().min(10)]),
-            "resolution=1920x1080".to_string(),
-            "os=Android".to_string(),
-        ];
+        let data: PlaylistDetailResponse = self.get_json(&url).await?;
 
-        if let Some(music_u) = &self.music_u {
-            cookie_parts.push(format!("MUSIC_U={music_u}"));
-        } else {
-            cookie_parts.push("MUSIC_A=4ee5f776c9ed1e4d5f031b09e084c6cb333e43ee4a841afeebbef9bbf4b7e4152b51ff20ecb9e8ee9e89ab23044cf50d1609e4781e805e73a138419e5583bc7fd1e5933c52368d9127ba9ce4e2f233bf5a77ba40ea6045ae1fc612ead95d7b0e0edf70a74334194e1a190979f5fc12e9968c3666a981495b33a649814e309366".to_string());
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
         }
 
-        cookie_parts.join("; ")
+        data.playlist
+            .ok_or_else(|| BotError::MusicApi("No playlist found".to_string()))
     }
 
-    fn eapi_splice(path: &str, json: &str) -> String {
-        let marker = "36cd479b6b5";
-        let text = format!("nobody{path}use{json}md5forencrypt");
-        let digest = format!("{:x}", md5_compute(text.as_bytes()));
-        format!("{path}-{marker}-{json}-{marker}-{digest}")
-    }
+    /// Hot or latest comments of a song, `limit` per page starting at `offset`
+    pub async fn get_comments(
+        &self,
+        song_id: u64,
+        sort: CommentSort,
+        limit: u32,
+        offset: u32,
+    ) -> Result<CommentPage> {
+        let resource = match sort {
+            CommentSort::Hot => "hotcomments",
+            CommentSort::Latest => "comments",
+        };
+        let url = format!(
+            "{}/api/v1/resource/{}/R_SO_4_{}?limit={}&offset={}",
+            self.base_url, resource, song_id, limit, offset
+        );
+        let data: CommentsResponse = self.get_json(&url).await?;
 
-    fn eapi_encrypt(data: &str) -> String {
-        let block_size = 16;
-        let data_len = data.len();
-        let padded_len = ((data_len + block_size) / block_size) * block_size;
-        let mut buf = vec![0u8; padded_len];
-        buf[..data_len].copy_from_slice(data.as_bytes());
-        let encrypted = Encryptor::<Aes128>::new_from_slice(b"e82ckenh8dichen8")
-            .expect("eapi key length")
-            .encrypt_padded_mut::<Pkcs7>(&mut buf, data_len)
-            .map_err(|_| BotError::MusicApi("Failed to encrypt eapi payload".to_string()))
-            .unwrap_or(&[]);
-        encode_upper(encrypted)
-    }
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
 
-    fn eapi_decrypt(hex_data: &str) -> Result<String> {
-        let mut bytes = hex::decode(hex_data).map_err(|e| BotError::MusicApi(e.to_string()))?;
-        let decrypted = Decryptor::<Aes128>::new_from_slice(b"e82ckenh8dichen8")
-            .expect("eapi key length")
-            .decrypt_padded_mut::<Pkcs7>(&mut bytes)
-            .map_err(|e| BotError::MusicApi(e.to_string()))?;
-        String::from_utf8(decrypted.to_vec()).map_err(|e| BotError::MusicApi(e.to_string()))
+        let comments = match sort {
+            CommentSort::Hot => data.hot_comments,
+            CommentSort::Latest => data.comments,
+        };
+        Ok(CommentPage {
+            comments,
+            total: data.total,
+            has_more: data.more,
+        })
     }
 
-    fn eapi_params(path: &str, json: &str) -> String {
-        let data = Self::eapi_splice(path, json);
-        let encrypted = Self::eapi_encrypt(&data);
-        format!("params={encrypted}")
+    /// Songs NetEase considers similar to a song
+    pub async fn get_similar_songs(&self, song_id: u64) -> Result<Vec<SearchSong>> {
+        let url = format!(
+            "{}/api/v1/discovery/simiSong?songid={}&limit=50&offset=0",
+            self.base_url, song_id
+        );
+        let data: SimilarSongsResponse = self.get_json(&url).await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        Ok(data.songs)
     }
 
-    fn choose_eapi_user_agent() -> &'static str {
-        "NeteaseMusic/9.3.40.1753206443(164);Dalvik/2.1.0 (Linux; U; Android 9; MIX 2 MIUI/V12.0.1.0.PDECNXM)"
+    /// All charts, official ones first
+    pub async fn get_toplists(&self) -> Result<Vec<Toplist>> {
+        let url = format!("{}/api/toplist", self.base_url);
+        let data: ToplistResponse = self.get_json(&url).await?;
+
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
+        }
+
+        Ok(data.list)
     }
 
-    /// Get song details
-    pub async fn get_song_detail(&self, song_id: u64) -> Result<SongDetail> {
-        let url = format!("{}/api/song/detail", self.base_url);
-        let mut params = HashMap::new();
-        params.insert("id", song_id.to_string());
-        params.insert("ids", format!("[{song_id}]"));
+    /// Get a chart with its tracks
+    pub async fn get_toplist(&self, toplist_id: u64) -> Result<Playlist> {
+        self.get_playlist(toplist_id).await
+    }
 
-        let mut request = self.client.post(url).form(&params);
+    /// Newly released albums, `limit` per page starting at `offset`
+    pub async fn get_new_albums(&self, limit: u32, offset: u32) -> Result<NewAlbumsResponse> {
+        let url = format!(
+            "{}/api/album/new?area=ALL&limit={}&offset={}&total=true",
+            self.base_url, limit, offset
+        );
+        let data: NewAlbumsResponse = self.get_json(&url).await?;
 
-        // Add MUSIC_U cookie if available
-        if let Some(music_u) = &self.music_u {
-            request = request.header("Cookie", format!("MUSIC_U={music_u}"));
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
         }
 
-        let response = request.send().await?;
-        let data: SongDetailResponse = response.json().await?;
+        Ok(data)
+    }
+
+    /// The user a login cookie belongs to, `None` once NetEase has logged it out
+    pub async fn get_account_profile(&self, music_u: &str) -> Result<Option<AccountProfile>> {
+        let url = format!("{}/api/w/nuser/account/get", self.base_url);
+        let data: AccountResponse = self.get_json_as(&url, music_u).await?;
 
         if data.code != 200 {
             return Err(BotError::MusicApi(format!(
@@ -225,27 +1352,55 @@ impl MusicApi {
             )));
         }
 
-        data.songs
-            .into_iter()
-            .next()
-            .ok_or_else(|| BotError::MusicApi("No song found".to_string()))
+        Ok(data.profile)
     }
 
-    /// Get song download URL
-    pub async fn get_song_url(&self, song_id: u64, br: u64) -> Result<SongUrl> {
-        let url = format!("{}/api/song/enhance/player/url", self.base_url);
-        let mut params = HashMap::new();
-        params.insert("ids", format!("[{song_id}]"));
-        params.insert("br", br.to_string());
-
-        let mut request = self.client.post(url).form(&params);
+    /// VIP level and expiry of the account behind a login cookie
+    pub async fn get_vip_info(&self, music_u: &str) -> Result<VipInfo> {
+        let url = format!("{}/api/music-vip-membership/client/vip/info", self.base_url);
+        let data: VipInfoResponse = self.get_json_as(&url, music_u).await?;
 
-        if let Some(music_u) = &self.music_u {
-            request = request.header("Cookie", format!("MUSIC_U={music_u}"));
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
         }
 
-        let response = request.send().await?;
-        let data: SongUrlResponse = response.json().await?;
+        let now = chrono::Utc::now().timestamp_millis();
+        Ok(data.data.map_or(
+            VipInfo {
+                level: 0,
+                expire_time: None,
+            },
+            |data| VipInfo {
+                level: data.red_vip_level,
+                expire_time: [data.associator, data.music_package]
+                    .into_iter()
+                    .flatten()
+                    .map(|package| package.expire_time)
+                    .filter(|&expire_time| expire_time > now)
+                    .max(),
+            },
+        ))
+    }
+
+    /// Renew a login. Returns the new `MUSIC_U` when NetEase issued one.
+    pub async fn refresh_login(&self, music_u: &str) -> Result<Option<String>> {
+        let response = self
+            .transport_request(
+                Transport::Weapi,
+                "/api/login/token/refresh",
+                &serde_json::json!({}),
+                Some(music_u),
+            )
+            .send()
+            .await?;
+        let music_u = response
+            .cookies()
+            .find(|cookie| cookie.name() == "MUSIC_U" && !cookie.value().is_empty())
+            .map(|cookie| cookie.value().to_string());
+        let data: CodeResponse = response.json().await?;
 
         if data.code != 200 {
             return Err(BotError::MusicApi(format!(
@@ -254,76 +1409,269 @@ impl MusicApi {
             )));
         }
 
-        data.data
-            .into_iter()
-            .next()
-            .ok_or_else(|| BotError::MusicApi("No download URL found".to_string()))
+        Ok(music_u)
     }
 
-    /// Get song lyrics
-    pub async fn get_song_lyric(&self, song_id: u64) -> Result<String> {
-        let url = format!("{}/api/song/lyric?id={}&lv=1&tv=1", self.base_url, song_id);
-
-        let mut request = self.client.get(&url);
+    /// Register a device anonymously. Returns its `MUSIC_A` token.
+    pub async fn register_anonymous(&self, device_id: &str) -> Result<String> {
+        let payload = serde_json::json!({ "username": anonymous_username(device_id) });
+        let response = self
+            .transport_request(Transport::Weapi, "/api/register/anonimous", &payload, None)
+            .send()
+            .await?;
+        let music_a = response
+            .cookies()
+            .find(|cookie| cookie.name() == "MUSIC_A" && !cookie.value().is_empty())
+            .map(|cookie| cookie.value().to_string());
+        let data: CodeResponse = response.json().await?;
 
-        if let Some(music_u) = &self.music_u {
-            request = request.header("Cookie", format!("MUSIC_U={music_u}"));
+        if data.code != 200 {
+            return Err(BotError::MusicApi(format!(
+                "API returned code {}",
+                data.code
+            )));
         }
 
-        let response = request.send().await?;
-        let data: LyricResponse = response.json().await?;
+        music_a.ok_or_else(|| BotError::MusicApi("Registration without MUSIC_A".to_string()))
+    }
 
-        if data.code != 200 {
+    /// Create a key for a QR code login, see `qr_login_url`
+    pub async fn create_qr_login_key(&self) -> Result<String> {
+        let payload = serde_json::json!({ "type": 1 });
+        let response = self
+            .transport_request(Transport::Weapi, "/api/login/qrcode/unikey", &payload, None)
+            .send()
+            .await?;
+        let data: QrKeyResponse = response.json().await?;
+
+        if data.code != 200 || data.unikey.is_empty() {
             return Err(BotError::MusicApi(format!(
                 "API returned code {}",
                 data.code
             )));
         }
 
-        let lyric = data
-            .lrc
-            .map_or_else(|| "No lyrics available".to_string(), |l| l.lyric);
+        Ok(data.unikey)
+    }
 
-        Ok(lyric)
+    /// Poll a QR code login. The cookie of a confirmed login comes from `Set-Cookie`.
+    pub async fn check_qr_login(&self, key: &str) -> Result<QrLoginStatus> {
+        let payload = serde_json::json!({ "key": key, "type": 1 });
+        let response = self
+            .transport_request(
+                Transport::Weapi,
+                "/api/login/qrcode/client/login",
+                &payload,
+                None,
+            )
+            .send()
+            .await?;
+        let music_u = response
+            .cookies()
+            .find(|cookie| cookie.name() == "MUSIC_U")
+            .map(|cookie| cookie.value().to_string());
+        let data: QrCheckResponse = response.json().await?;
+
+        match data.code {
+            800 => Ok(QrLoginStatus::Expired),
+            801 => Ok(QrLoginStatus::Waiting),
+            802 => Ok(QrLoginStatus::Scanned {
+                nickname: data.nickname,
+            }),
+            803 => music_u
+                .map(|music_u| QrLoginStatus::Confirmed { music_u })
+                .ok_or_else(|| BotError::MusicApi("Login confirmed without MUSIC_U".to_string())),
+            code => Err(BotError::MusicApi(format!(
+                "API returned code {code}: {}",
+                data.message.unwrap_or_default()
+            ))),
+        }
     }
 
-    /// Search songs
-    pub async fn search_songs(&self, keyword: &str, limit: u32) -> Result<Vec<SearchSong>> {
-        let path = "/api/v1/search/song/get";
-        let url = format!("{}/eapi/v1/search/song/get", self.base_url);
-        let payload = serde_json::json!({
-            "s": keyword,
-            "offset": 0,
-            "limit": limit.max(1),
-        });
-        let payload_str = payload.to_string();
-        let body = Self::eapi_params(path, &payload_str);
-        let request = self
+    /// Send a request with the best account of the pool and return the JSON body.
+    /// When NetEase rejects the account itself, the request is retried with the next one.
+    async fn send_with_account<F, Fut>(&self, send: F) -> Result<String>
+    where
+        F: Fn(Option<String>) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let mut tried = Vec::new();
+        let mut account = self
+            .user_account
+            .clone()
+            .or_else(|| self.accounts.pick(&tried));
+        loop {
+            let body = send(account.as_ref().map(|account| account.music_u())).await?;
+            let Some(current) = account else {
+                return Ok(body);
+            };
+            match serde_json::from_str::<CodeResponse>(&body) {
+                Ok(CodeResponse { code }) if is_account_rejection(code) => {
+                    current.record_rejection(code);
+                    tried.push(current.id());
+                    account = self.accounts.pick(&tried);
+                    tracing::warn!(
+                        "NetEase rejected account {} with code {}{}",
+                        current.label(),
+                        code,
+                        if account.is_some() {
+                            ", retrying with another account"
+                        } else {
+                            ""
+                        }
+                    );
+                    if account.is_none() {
+                        return Ok(body);
+                    }
+                }
+                _ => return Ok(body),
+            }
+        }
+    }
+
+    /// GET a JSON API with the `MUSIC_U` cookie of the pool
+    async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let body = self
+            .send_with_account(|music_u| async move {
+                let mut request = self.client.get(url);
+                if let Some(music_u) = music_u {
+                    request = request.header("Cookie", format!("MUSIC_U={music_u}"));
+                }
+                Ok(request.send().await?.text().await?)
+            })
+            .await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// GET a JSON API as a specific account
+    async fn get_json_as<T: DeserializeOwned>(&self, url: &str, music_u: &str) -> Result<T> {
+        let response = self
             .client
-            .post(url)
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .header("User-Agent", Self::choose_eapi_user_agent())
-            .header("Cookie", self.build_eapi_cookie())
-            .body(body);
+            .get(url)
+            .header("Cookie", format!("MUSIC_U={music_u}"))
+            .send()
+            .await?;
+        Ok(response.json().await?)
+    }
 
-        let response = request.send().await?;
-        let raw_body = response.text().await?;
-        let trimmed = raw_body.trim_start();
-        let data: EapiSearchResponse = if trimmed.starts_with('{') {
-            serde_json::from_str(trimmed)?
-        } else {
-            let decrypted = Self::eapi_decrypt(trimmed)?;
-            serde_json::from_str(&decrypted)?
+    /// POST request of an endpoint over `transport`; `path` is its `/api/...` path
+    fn transport_request(
+        &self,
+        transport: Transport,
+        path: &str,
+        payload: &serde_json::Value,
+        music_u: Option<&str>,
+    ) -> RequestBuilder {
+        let endpoint = path.trim_start_matches("/api");
+        let request = match transport {
+            Transport::Api => self
+                .client
+                .post(format!("{}{path}", self.base_url))
+                .form(&form_fields(payload)),
+            Transport::Weapi => {
+                let form = crypto::weapi(&payload.to_string());
+                self.client
+                    .post(format!("{}/weapi{endpoint}", self.base_url))
+                    .header("Referer", "https://music.163.com/")
+                    .form(&[("params", form.params), ("encSecKey", form.enc_sec_key)])
+            }
+            Transport::Eapi => self
+                .client
+                .post(format!("{}/eapi{endpoint}", self.base_url))
+                .header("User-Agent", &self.profile.user_agent)
+                .form(&[("params", crypto::eapi(path, &payload.to_string()))]),
+            Transport::Linuxapi => {
+                let text = serde_json::json!({
+                    "method": "POST",
+                    "url": format!("https://music.163.com{path}"),
+                    "params": payload,
+                });
+                self.client
+                    .post(format!("{}/api/linux/forward", self.base_url))
+                    .header("User-Agent", LINUXAPI_USER_AGENT)
+                    .form(&[("eparams", crypto::linuxapi(&text.to_string()))])
+            }
         };
 
-        if data.code != 200 {
-            return Err(BotError::MusicApi(format!(
-                "API returned code {}",
-                data.code
-            )));
+        // eapi carries the device identity in its cookie
+        if transport == Transport::Eapi {
+            request.header("Cookie", self.build_eapi_cookie(music_u))
+        } else if let Some(music_u) = music_u {
+            request.header("Cookie", format!("MUSIC_U={music_u}"))
+        } else {
+            request
+        }
+    }
+
+    /// Call an endpoint over `transport` with the `MUSIC_U` cookie of the pool;
+    /// `path` is its `/api/...` path
+    async fn call<T: DeserializeOwned>(
+        &self,
+        transport: Transport,
+        path: &str,
+        payload: &serde_json::Value,
+    ) -> Result<T> {
+        if transport == Transport::Eapi {
+            self.ensure_registered().await;
+        }
+        let json = self
+            .send_with_account(|music_u| async move {
+                let response = self
+                    .transport_request(transport, path, payload, music_u.as_deref())
+                    .send()
+                    .await?;
+                let body = response.text().await?;
+                let trimmed = body.trim_start();
+                if transport == Transport::Eapi && !trimmed.starts_with('{') {
+                    crypto::eapi_decrypt(trimmed)
+                } else {
+                    Ok(body)
+                }
+            })
+            .await?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Resolve a share short link (`163cn.tv`, `163cn.link`) by following its redirect
+    /// chain up to the first `music.163.com` URL. Response bodies are never read.
+    pub async fn resolve_short_link(&self, short_url: &str) -> Result<NeteaseLink> {
+        if let Some(link) = self.short_links.get(&short_url.to_string()) {
+            return Ok(link);
+        }
+
+        let mut current = reqwest::Url::parse(short_url)
+            .map_err(|e| BotError::Parse(format!("Invalid short link {short_url}: {e}")))?;
+
+        for _ in 0..MAX_REDIRECTS {
+            if let Some(link) = NeteaseLink::parse_url(current.as_str()) {
+                self.short_links.insert(short_url.to_string(), link);
+                return Ok(link);
+            }
+
+            let response = self.redirect_client.get(current.clone()).send().await?;
+            if !response.status().is_redirection() {
+                return Err(BotError::MusicApi(format!(
+                    "Short link {short_url} ended at {current} ({}) without a NetEase link",
+                    response.status()
+                )));
+            }
+
+            let location = response
+                .headers()
+                .get(reqwest::header::LOCATION)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| {
+                    BotError::MusicApi(format!("Redirect from {current} has no Location header"))
+                })?;
+            current = current
+                .join(location)
+                .map_err(|e| BotError::Parse(format!("Invalid redirect target {location}: {e}")))?;
+            tracing::debug!("Short link {} redirected to {}", short_url, current);
         }
 
-        Ok(data.result.songs)
+        Err(BotError::MusicApi(format!(
+            "Short link {short_url} redirected more than {MAX_REDIRECTS} times"
+        )))
     }
 
     /// Download file with proper headers and cookies
@@ -339,7 +1687,7 @@ impl MusicApi {
         let mut request = self.client.get(&processed_url);
 
         // Add MUSIC_U cookie if available
-        if let Some(music_u) = &self.music_u {
+        if let Some(music_u) = self.accounts.pick(&[]).map(|account| account.music_u()) {
             request = request.header("Cookie", format!("MUSIC_U={music_u}"));
         }
 
@@ -429,6 +1777,41 @@ pub fn format_artists(artists: &[Artist]) -> String {
         .join("/")
 }
 
+/// Render `data` as a black on white QR code PNG
+pub fn qr_code_png(data: &str) -> Result<Vec<u8>> {
+    use image::{GrayImage, Luma};
+
+    /// Pixels per module
+    const SCALE: u32 = 8;
+    /// Modules of white border around the code
+    const QUIET_ZONE: u32 = 4;
+
+    let code = qrcode::QrCode::new(data.as_bytes())
+        .map_err(|e| BotError::MusicApi(format!("Failed to encode QR code: {e}")))?;
+    let width = code.width() as u32;
+    let colors = code.to_colors();
+
+    let size = (width + 2 * QUIET_ZONE) * SCALE;
+    let image = GrayImage::from_fn(size, size, |x, y| {
+        let (x, y) = (x / SCALE, y / SCALE);
+        let inside = (QUIET_ZONE..QUIET_ZONE + width).contains(&x)
+            && (QUIET_ZONE..QUIET_ZONE + width).contains(&y);
+        let dark = inside
+            && colors[((y - QUIET_ZONE) * width + x - QUIET_ZONE) as usize] == qrcode::Color::Dark;
+        if dark {
+            Luma([0])
+        } else {
+            Luma([255])
+        }
+    });
+
+    let mut cursor = Cursor::new(Vec::new());
+    DynamicImage::ImageLuma8(image)
+        .write_to(&mut cursor, ImageFormat::Png)
+        .map_err(|e| BotError::MusicApi(format!("Failed to encode QR code image: {e}")))?;
+    Ok(cursor.into_inner())
+}
+
 /// Resize image with black padding to maintain aspect ratio (like the original Go project)
 fn resize_image_with_padding(
     img: DynamicImage,
@@ -477,3 +1860,214 @@ fn resize_image_with_padding(
 
     DynamicImage::ImageRgb8(canvas)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve `routes` (path -> Location) as 302 redirects, answering 404 otherwise
+    async fn redirect_server(routes: Vec<(&'static str, String)>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    break;
+                };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                let response = match routes.iter().find(|(route, _)| *route == path) {
+                    Some((_, location)) => format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    ),
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string(),
+                };
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+
+        (base, hits)
+    }
+
+    #[test]
+    fn test_parse_search_query() {
+        assert_eq!(
+            SearchType::parse_query("album: 范特西"),
+            (SearchType::Album, "范特西")
+        );
+        assert_eq!(
+            SearchType::parse_query("歌词：后来 我总算学会了"),
+            (SearchType::Lyric, "后来 我总算学会了")
+        );
+        assert_eq!(SearchType::parse_query("MV:晴天"), (SearchType::Mv, "晴天"));
+        assert_eq!(
+            SearchType::parse_query("Re:Zero"),
+            (SearchType::Song, "Re:Zero")
+        );
+        assert_eq!(
+            SearchType::parse_query(" 晴天 "),
+            (SearchType::Song, "晴天")
+        );
+    }
+
+    #[test]
+    fn test_lyric_snippet() {
+        let song: SearchSong = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "后来",
+            "ar": [{"id": 2, "name": "刘若英"}],
+            "al": {"id": 3, "name": "我等你", "picUrl": null},
+            "dt": 341_000,
+            "lyrics": {"txt": "作词 : 施人诚\n后来 我总算学会了 如何去爱\n可惜你早已远去"}
+        }))
+        .unwrap();
+        assert_eq!(
+            song.lyric_snippet("学会了").as_deref(),
+            Some("后来 我总算学会了 如何去爱")
+        );
+
+        let song = SearchSong {
+            lyrics: Some(serde_json::json!(["<b>晴天</b>", "刮风这天"])),
+            ..song
+        };
+        assert_eq!(song.lyric_snippet("雨").as_deref(), Some("晴天"));
+    }
+
+    #[test]
+    fn test_fee_label() {
+        let song = |fee: i64, privilege: serde_json::Value| -> SearchSong {
+            serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "name": "晴天",
+                "ar": [{"id": 2, "name": "周杰伦"}],
+                "al": {"id": 3, "name": "叶惠美", "picUrl": null},
+                "dt": 269_000,
+                "fee": fee,
+                "privilege": privilege,
+            }))
+            .unwrap()
+        };
+        let playable = serde_json::json!({"id": 1, "st": 0, "pl": 320_000});
+
+        assert_eq!(song(0, playable.clone()).fee_label(), None);
+        assert_eq!(song(1, playable.clone()).fee_label(), Some("VIP"));
+        assert_eq!(song(4, playable.clone()).fee_label(), Some("付费"));
+        assert_eq!(song(8, playable).fee_label(), Some("高音质VIP"));
+        assert_eq!(song(1, serde_json::Value::Null).fee_label(), Some("VIP"));
+
+        // Only a preview is playable with the bot's account
+        let trial = serde_json::json!({
+            "id": 1, "st": 0, "pl": 128_000, "freeTrialInfo": {"start": 0, "end": 30}
+        });
+        assert_eq!(song(1, trial).fee_label(), Some("试听"));
+        let locked = serde_json::json!({"id": 1, "st": 0, "pl": 0, "freeTrialInfo": null});
+        assert_eq!(song(4, locked).fee_label(), Some("试听"));
+        let removed = serde_json::json!({"id": 1, "st": -200, "pl": 0});
+        assert_eq!(song(0, removed).fee_label(), Some("无版权"));
+    }
+
+    #[test]
+    fn test_song_qualities() {
+        let song: SongDetail = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "晴天",
+            "hr": null,
+            "sq": {"br": 1_411_000, "size": 30_000_000},
+            "h": {"br": 320_000, "size": 10_000_000},
+            "m": {"br": 192_000, "size": 0},
+            "l": {"br": 128_000, "size": 4_000_000}
+        }))
+        .unwrap();
+        assert_eq!(
+            song.qualities(),
+            vec![
+                (999_000, 30_000_000),
+                (320_000, 10_000_000),
+                (128_000, 4_000_000)
+            ]
+        );
+
+        // The old detail API names them hMusic/mMusic/lMusic with a bitrate
+        let song: SongDetail = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "晴天",
+            "hMusic": {"bitrate": 320_000, "size": 10_000_000}
+        }))
+        .unwrap();
+        assert_eq!(song.qualities(), vec![(320_000, 10_000_000)]);
+    }
+
+    #[test]
+    fn test_song_url_level() {
+        let url = |br: u64, format: &str| SongUrl {
+            id: 1,
+            url: "http://m701.music.126.net/1.mp3".to_string(),
+            br,
+            size: 1,
+            md5: String::new(),
+            format: format.to_string(),
+        };
+        // A lossless file with a variable bitrate
+        assert_eq!(url(905_000, "flac").level(999_000), 999_000);
+        // Lossless requested, but only 320k served
+        assert_eq!(url(320_000, "mp3").level(999_000), 320_000);
+        assert_eq!(url(320_002, "mp3").level(320_000), 320_000);
+        assert_eq!(url(192_000, "mp3").level(320_000), 192_000);
+        assert_eq!(url(96_000, "mp3").level(128_000), 128_000);
+    }
+
+    #[test]
+    fn test_qr_code_png() {
+        let png = qr_code_png(&qr_login_url("1234567890abcdef")).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_luma8();
+        assert_eq!(image.width(), image.height());
+        // Quiet zone is white, the finder pattern in the top left corner is dark
+        assert_eq!(image.get_pixel(0, 0).0, [255]);
+        assert_eq!(image.get_pixel(4 * 8, 4 * 8).0, [0]);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_short_link_follows_redirects() {
+        let (base, hits) = redirect_server(vec![
+            ("/abc123", "/hop".to_string()),
+            (
+                "/hop",
+                "https://y.music.163.com/m/song?id=1234567&userid=42".to_string(),
+            ),
+        ])
+        .await;
+        let api = MusicApi::new(&[], String::new(), ClientProfile::default());
+        let short_url = format!("{base}/abc123");
+
+        let link = api.resolve_short_link(&short_url).await.unwrap();
+        assert_eq!(link, NeteaseLink::Song(1_234_567));
+        // The final music.163.com URL itself is never requested
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // Second lookup is served from the cache
+        let link = api.resolve_short_link(&short_url).await.unwrap();
+        assert_eq!(link, NeteaseLink::Song(1_234_567));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_short_link_without_netease_target() {
+        let (base, _) = redirect_server(vec![("/abc123", "/missing".to_string())]).await;
+        let api = MusicApi::new(&[], String::new(), ClientProfile::default());
+
+        assert!(api
+            .resolve_short_link(&format!("{base}/abc123"))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,331 @@
+//! Ranking of search results against a free text query
+//!
+//! NetEase often puts covers, live recordings or karaoke tracks first. Results are scored
+//! by title and artist similarity, alternative versions the query did not ask for are
+//! down-ranked and the original search order only breaks near ties.
+
+use crate::music_api::SearchSong;
+
+/// Search results considered when ranking
+pub const CANDIDATES: u32 = 10;
+/// Results whose scores differ by less than this are considered a tie
+pub const CLOSE_MARGIN: f64 = 0.05;
+
+/// Weight of the original search order, spread over all candidates
+const POSITION_WEIGHT: f64 = 0.1;
+/// Penalty for a version the query did not ask for
+const VARIANT_PENALTY: f64 = 0.3;
+
+/// Markers of alternative versions, matched against bracketed title suffixes and albums.
+/// Latin markers only match whole words, so "inst" does not hit "Instant Crush".
+const VARIANT_MARKERS: &[&str] = &[
+    "live",
+    "伴奏",
+    "翻自",
+    "cover",
+    "karaoke",
+    "instrumental",
+    "inst",
+    "remix",
+    "dj版",
+    "demo",
+    "现场",
+    "纯音乐",
+    "试听",
+];
+
+/// A search query, optionally split into artist and title (`周杰伦 - 晴天`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SongQuery {
+    pub text: String,
+    pub title: String,
+    pub artist: Option<String>,
+}
+
+impl SongQuery {
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        for separator in [" - ", " – ", " — ", " / "] {
+            if let Some((artist, title)) = text.split_once(separator) {
+                let (artist, title) = (artist.trim(), title.trim());
+                if !artist.is_empty() && !title.is_empty() {
+                    return Self {
+                        text: text.to_string(),
+                        title: title.to_string(),
+                        artist: Some(artist.to_string()),
+                    };
+                }
+            }
+        }
+        Self {
+            text: text.to_string(),
+            title: text.to_string(),
+            artist: None,
+        }
+    }
+}
+
+/// Score of `song` at position `index` of `total` results, higher is better
+#[must_use]
+pub fn score(query: &SongQuery, song: &SearchSong, index: usize, total: usize) -> f64 {
+    let name = normalize(&strip_brackets(&song.name));
+    let artists: Vec<String> = song.artists.iter().map(|a| normalize(&a.name)).collect();
+
+    let base = if let Some(artist) = &query.artist {
+        let title = normalize(&query.title);
+        let artist = normalize(artist);
+        let as_written = title_artist_score(&title, &artist, &name, &artists);
+        // People write both "artist - title" and "title - artist"
+        let swapped = title_artist_score(&artist, &title, &name, &artists);
+        as_written.max(swapped - 0.05)
+    } else {
+        let text = normalize(&query.text);
+        // Artist names mentioned anywhere in the query count as the artist part
+        let mentioned = artists
+            .iter()
+            .filter(|artist| !artist.is_empty() && text.contains(artist.as_str()))
+            .max_by_key(|artist| artist.chars().count());
+        match mentioned {
+            Some(artist) if text != *artist => {
+                let title = text.replacen(artist.as_str(), "", 1);
+                0.7 * similarity(&title, &name) + 0.3
+            }
+            _ => similarity(&text, &name),
+        }
+    };
+
+    let (_, suffixes) = split_brackets(&song.name);
+    let is_variant = VARIANT_MARKERS.iter().any(|marker| {
+        !has_marker(&query.text, marker)
+            && (has_marker(&suffixes, marker) || has_marker(&song.album.name, marker))
+    });
+    let penalty = if is_variant { VARIANT_PENALTY } else { 0.0 };
+
+    let position = if total > 1 {
+        POSITION_WEIGHT * (1.0 - index as f64 / total as f64)
+    } else {
+        POSITION_WEIGHT
+    };
+
+    base - penalty + position
+}
+
+/// Rank songs best first. Equal scores keep the original search order.
+#[must_use]
+pub fn rank<'a>(query: &SongQuery, songs: &'a [SearchSong]) -> Vec<(&'a SearchSong, f64)> {
+    let mut ranked: Vec<(&SearchSong, f64)> = songs
+        .iter()
+        .enumerate()
+        .map(|(i, song)| (song, score(query, song, i, songs.len())))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+/// Candidates whose score is within `CLOSE_MARGIN` of the best one
+#[must_use]
+pub fn close_matches<'a, 'b>(ranked: &'b [(&'a SearchSong, f64)]) -> &'b [(&'a SearchSong, f64)] {
+    let Some((_, best)) = ranked.first() else {
+        return ranked;
+    };
+    let count = ranked
+        .iter()
+        .take_while(|(_, score)| best - score < CLOSE_MARGIN)
+        .count();
+    &ranked[..count]
+}
+
+fn title_artist_score(title: &str, artist: &str, name: &str, artists: &[String]) -> f64 {
+    let artist_score = artists
+        .iter()
+        .map(|candidate| similarity(artist, candidate))
+        .fold(0.0, f64::max);
+    0.6 * similarity(title, name) + 0.4 * artist_score
+}
+
+/// Lowercase and keep only letters and digits, so punctuation and spacing do not matter
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether `text` mentions `marker`: Latin markers as whole words, others anywhere
+fn has_marker(text: &str, marker: &str) -> bool {
+    if marker.is_ascii() {
+        text.to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|word| word == marker)
+    } else {
+        normalize(text).contains(marker)
+    }
+}
+
+/// Split `text` into the part outside brackets and its bracketed suffixes like
+/// `(Live)` or `（伴奏）`, the latter separated by spaces
+fn split_brackets(text: &str) -> (String, String) {
+    let mut outside = String::new();
+    let mut inside = String::new();
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' | '（' | '[' | '【' | '<' | '《' => depth += 1,
+            ')' | '）' | ']' | '】' | '>' | '》' => {
+                depth = depth.saturating_sub(1);
+                inside.push(' ');
+            }
+            _ if depth == 0 => outside.push(c),
+            _ => inside.push(c),
+        }
+    }
+    (outside, inside)
+}
+
+/// Drop bracketed suffixes like `(Live)` or `（伴奏）`
+fn strip_brackets(text: &str) -> String {
+    let (outside, _) = split_brackets(text);
+    if outside.trim().is_empty() {
+        text.to_string()
+    } else {
+        outside
+    }
+}
+
+/// Similarity of two normalized strings in `0.0..=1.0`
+fn similarity(a: &str, b: &str) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+
+    let (len_a, len_b) = (a.chars().count(), b.chars().count());
+    if a.contains(b) || b.contains(a) {
+        return 0.6 + 0.3 * len_a.min(len_b) as f64 / len_a.max(len_b) as f64;
+    }
+
+    // Dice coefficient over character bigrams (single characters for very short strings)
+    let grams = |s: &str| -> Vec<String> {
+        let chars: Vec<char> = s.chars().collect();
+        if chars.len() < 2 {
+            return vec![s.to_string()];
+        }
+        chars.windows(2).map(|w| w.iter().collect()).collect()
+    };
+    let grams_a = grams(a);
+    let mut grams_b = grams(b);
+    let total = grams_a.len() + grams_b.len();
+    let mut shared = 0usize;
+    for gram in &grams_a {
+        if let Some(pos) = grams_b.iter().position(|g| g == gram) {
+            grams_b.swap_remove(pos);
+            shared += 1;
+        }
+    }
+    0.8 * (2 * shared) as f64 / total as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music_api::{Album, Artist};
+
+    fn song(id: u64, name: &str, artist: &str, album: &str) -> SearchSong {
+        SearchSong {
+            id,
+            name: name.to_string(),
+            artists: vec![Artist {
+                id,
+                name: artist.to_string(),
+            }],
+            album: Album {
+                id,
+                name: album.to_string(),
+                pic_url: None,
+            },
+            duration: 0,
+            fee: 0,
+            privilege: None,
+            lyrics: None,
+        }
+    }
+
+    fn best(query: &str, songs: &[SearchSong]) -> u64 {
+        rank(&SongQuery::parse(query), songs)[0].0.id
+    }
+
+    #[test]
+    fn test_parse_structured_query() {
+        let query = SongQuery::parse("周杰伦 - 晴天");
+        assert_eq!(query.artist.as_deref(), Some("周杰伦"));
+        assert_eq!(query.title, "晴天");
+
+        let query = SongQuery::parse("晴天");
+        assert_eq!(query.artist, None);
+        assert_eq!(query.title, "晴天");
+    }
+
+    #[test]
+    fn test_variants_are_down_ranked() {
+        let songs = [
+            song(1, "晴天 (Live)", "周杰伦", "地表最强演唱会"),
+            song(2, "晴天 (伴奏)", "周杰伦", "叶惠美"),
+            song(3, "晴天（翻自 周杰伦）", "某歌手", "翻唱集"),
+            song(4, "晴天", "周杰伦", "叶惠美"),
+        ];
+        assert_eq!(best("晴天", &songs), 4);
+        // Unless the query asks for them
+        assert_eq!(best("晴天 live", &songs), 1);
+    }
+
+    #[test]
+    fn test_marker_letters_inside_words() {
+        for (name, album) in [
+            ("Instant Crush", "Random Access Memories"),
+            ("Delivered", "Oliver"),
+            ("Demons", "Night Visions"),
+            ("One More Time", "Discovery"),
+        ] {
+            let query = SongQuery::parse(name);
+            let score = score(&query, &song(1, name, "歌手", album), 0, 1);
+            assert!((score - (1.0 + POSITION_WEIGHT)).abs() < 1e-9, "{name}");
+        }
+
+        let songs = [
+            song(1, "Instant Crush (Live)", "Daft Punk", "Alive 2017"),
+            song(2, "Instant Crush", "Daft Punk", "Live Sessions"),
+            song(3, "Instant Crush", "Daft Punk", "Random Access Memories"),
+        ];
+        assert_eq!(best("Instant Crush", &songs), 3);
+        assert_eq!(best("Instant Crush live", &songs), 1);
+    }
+
+    #[test]
+    fn test_artist_matching() {
+        let songs = [
+            song(1, "后来", "张三", "翻唱"),
+            song(2, "后来", "刘若英", "我等你"),
+        ];
+        assert_eq!(best("刘若英 - 后来", &songs), 2);
+        assert_eq!(best("后来 - 刘若英", &songs), 2);
+        assert_eq!(best("刘若英 后来", &songs), 2);
+    }
+
+    #[test]
+    fn test_close_matches() {
+        let songs = [
+            song(1, "晴天", "周杰伦", "叶惠美"),
+            song(2, "晴天", "张三", "单曲"),
+            song(3, "阴天", "莫文蔚", "阴天"),
+        ];
+        let ranked = rank(&SongQuery::parse("晴天"), &songs);
+        let close: Vec<u64> = close_matches(&ranked).iter().map(|(s, _)| s.id).collect();
+        assert_eq!(close, vec![1, 2]);
+
+        let ranked = rank(&SongQuery::parse("周杰伦 - 晴天"), &songs);
+        assert_eq!(close_matches(&ranked).len(), 1);
+    }
+}
@@ -0,0 +1,404 @@
+//! Outbound message scheduler that respects Telegram flood control
+//!
+//! Every message the bot sends goes through [`SendQueue`], which enforces:
+//! - a global limit of `GLOBAL_PER_SECOND` messages per second
+//! - at most one message per second in any chat
+//! - at most `GROUP_PER_MINUTE` messages per minute in groups and channels
+//!
+//! Deliveries take precedence over progress updates (`edit_message_text`): progress
+//! updates wait while a delivery to the same chat is pending and may only use part of
+//! the global budget. `RetryAfter` errors pause the affected chat and re-send the
+//! request automatically.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use teloxide::requests::{Output, Request};
+use teloxide::types::ChatId;
+use teloxide::RequestError;
+
+/// Messages per second across all chats
+const GLOBAL_PER_SECOND: usize = 30;
+/// Share of the global budget progress updates may use
+const PROGRESS_PER_SECOND: usize = 20;
+/// Minimum interval between two messages in the same chat
+const CHAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Messages per minute in a single group or channel
+const GROUP_PER_MINUTE: usize = 20;
+/// How many times a request is re-sent after `RetryAfter`
+const MAX_RETRIES: u32 = 3;
+/// Poll interval for progress updates waiting behind deliveries
+const YIELD_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Scheduling priority of an outbound request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Replies, audio and everything else the user is waiting for
+    Delivery,
+    /// Progress updates that may be delayed behind deliveries
+    Progress,
+}
+
+#[derive(Debug, Default)]
+struct ChatWindow {
+    sent: VecDeque<Instant>,
+    blocked_until: Option<Instant>,
+    deliveries_waiting: usize,
+}
+
+#[derive(Debug, Default)]
+struct Limits {
+    global: VecDeque<Instant>,
+    global_blocked_until: Option<Instant>,
+    chats: HashMap<ChatId, ChatWindow>,
+}
+
+impl Limits {
+    /// Time to wait before a message to `chat` may be sent, or `None` to send now
+    fn wait_time(
+        &mut self,
+        chat: Option<ChatId>,
+        priority: Priority,
+        now: Instant,
+    ) -> Option<Duration> {
+        while self
+            .global
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= Duration::from_secs(1))
+        {
+            self.global.pop_front();
+        }
+
+        let mut wait = Duration::ZERO;
+        if let Some(until) = self.global_blocked_until {
+            wait = wait.max(until.saturating_duration_since(now));
+        }
+        let global_limit = match priority {
+            Priority::Delivery => GLOBAL_PER_SECOND,
+            Priority::Progress => PROGRESS_PER_SECOND,
+        };
+        if self.global.len() >= global_limit {
+            let oldest = self.global[self.global.len() - global_limit];
+            wait = wait.max((oldest + Duration::from_secs(1)).saturating_duration_since(now));
+        }
+
+        if let Some(chat) = chat {
+            let window = self.chats.entry(chat).or_default();
+            while window
+                .sent
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= Duration::from_mins(1))
+            {
+                window.sent.pop_front();
+            }
+            if let Some(until) = window.blocked_until {
+                wait = wait.max(until.saturating_duration_since(now));
+            }
+            if priority == Priority::Progress && window.deliveries_waiting > 0 {
+                wait = wait.max(YIELD_INTERVAL);
+            }
+            if let Some(last) = window.sent.back() {
+                wait = wait.max((*last + CHAT_INTERVAL).saturating_duration_since(now));
+            }
+            // Negative IDs are groups, supergroups and channels
+            if chat.0 < 0 && window.sent.len() >= GROUP_PER_MINUTE {
+                let oldest = window.sent[window.sent.len() - GROUP_PER_MINUTE];
+                wait = wait.max((oldest + Duration::from_mins(1)).saturating_duration_since(now));
+            }
+        }
+
+        (!wait.is_zero()).then_some(wait)
+    }
+
+    fn record(&mut self, chat: Option<ChatId>, now: Instant) {
+        self.global.push_back(now);
+        if let Some(chat) = chat {
+            self.chats.entry(chat).or_default().sent.push_back(now);
+        }
+    }
+
+    fn block(&mut self, chat: Option<ChatId>, until: Instant) {
+        match chat {
+            Some(chat) => self.chats.entry(chat).or_default().blocked_until = Some(until),
+            None => self.global_blocked_until = Some(until),
+        }
+    }
+
+    /// Drop windows of chats that have been idle for a while
+    fn prune(&mut self, now: Instant) {
+        self.chats.retain(|_, window| {
+            window.deliveries_waiting > 0
+                || window.blocked_until.is_some_and(|until| until > now)
+                || window
+                    .sent
+                    .back()
+                    .is_some_and(|t| now.duration_since(*t) < Duration::from_mins(1))
+        });
+    }
+}
+
+/// Central rate limiter for everything the bot sends
+#[derive(Debug, Default)]
+pub struct SendQueue {
+    limits: Mutex<Limits>,
+}
+
+impl SendQueue {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send a delivery to `chat` (replies, audio, documents)
+    pub async fn deliver<R>(&self, chat: ChatId, request: R) -> Result<Output<R>, RequestError>
+    where
+        R: Request<Err = RequestError>,
+    {
+        self.send(Some(chat), Priority::Delivery, request).await
+    }
+
+    /// Send a low priority progress update to `chat` (status message edits)
+    pub async fn progress<R>(&self, chat: ChatId, request: R) -> Result<Output<R>, RequestError>
+    where
+        R: Request<Err = RequestError>,
+    {
+        self.send(Some(chat), Priority::Progress, request).await
+    }
+
+    /// Send a request once the rate limits allow it, re-sending after `RetryAfter`
+    pub async fn send<R>(
+        &self,
+        chat: Option<ChatId>,
+        priority: Priority,
+        request: R,
+    ) -> Result<Output<R>, RequestError>
+    where
+        R: Request<Err = RequestError>,
+    {
+        let mut attempt = 0;
+        loop {
+            self.acquire(chat, priority).await;
+            match request.send_ref().await {
+                Err(RequestError::RetryAfter(retry_after)) if attempt < MAX_RETRIES => {
+                    attempt += 1;
+                    tracing::warn!(
+                        "Flood control hit (chat {:?}), retrying in {:?} (attempt {}/{})",
+                        chat,
+                        retry_after,
+                        attempt,
+                        MAX_RETRIES
+                    );
+                    self.limits
+                        .lock()
+                        .unwrap()
+                        .block(chat, Instant::now() + retry_after);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Wait until a message to `chat` may be sent and reserve the slot
+    async fn acquire(&self, chat: Option<ChatId>, priority: Priority) {
+        let _delivery = match (priority, chat) {
+            (Priority::Delivery, Some(chat)) => Some(DeliveryGuard::new(self, chat)),
+            _ => None,
+        };
+
+        loop {
+            let wait = {
+                let mut limits = self.limits.lock().unwrap();
+                let now = Instant::now();
+                let Some(wait) = limits.wait_time(chat, priority, now) else {
+                    limits.record(chat, now);
+                    if limits.chats.len() > 1024 {
+                        limits.prune(now);
+                    }
+                    return;
+                };
+                wait
+            };
+
+            // Progress updates re-check regularly so deliveries can overtake them
+            let wait = match priority {
+                Priority::Delivery => wait,
+                Priority::Progress => wait.min(YIELD_INTERVAL),
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Marks a delivery to a chat as pending so progress updates there yield to it
+struct DeliveryGuard<'a> {
+    queue: &'a SendQueue,
+    chat: ChatId,
+}
+
+impl<'a> DeliveryGuard<'a> {
+    fn new(queue: &'a SendQueue, chat: ChatId) -> Self {
+        let mut limits = queue.limits.lock().unwrap();
+        limits.chats.entry(chat).or_default().deliveries_waiting += 1;
+        Self { queue, chat }
+    }
+}
+
+impl Drop for DeliveryGuard<'_> {
+    fn drop(&mut self) {
+        let mut limits = self.queue.limits.lock().unwrap();
+        if let Some(window) = limits.chats.get_mut(&self.chat) {
+            window.deliveries_waiting = window.deliveries_waiting.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::future::{Future, IntoFuture};
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use teloxide::payloads::DeleteMessage;
+    use teloxide::requests::HasPayload;
+    use teloxide::types::{MessageId, True};
+
+    const RETRY_AFTER: Duration = Duration::from_millis(50);
+
+    type Response = Pin<Box<dyn Future<Output = Result<True, RequestError>> + Send>>;
+
+    /// Request that hits flood control for its first `failures` attempts
+    struct FloodedRequest {
+        payload: DeleteMessage,
+        attempts: Arc<AtomicUsize>,
+        failures: usize,
+    }
+
+    impl FloodedRequest {
+        fn new(failures: usize) -> Self {
+            Self {
+                payload: DeleteMessage::new(ChatId(42), MessageId(1)),
+                attempts: Arc::new(AtomicUsize::new(0)),
+                failures,
+            }
+        }
+    }
+
+    impl HasPayload for FloodedRequest {
+        type Payload = DeleteMessage;
+
+        fn payload_mut(&mut self) -> &mut DeleteMessage {
+            &mut self.payload
+        }
+
+        fn payload_ref(&self) -> &DeleteMessage {
+            &self.payload
+        }
+    }
+
+    impl Request for FloodedRequest {
+        type Err = RequestError;
+        type Send = Response;
+        type SendRef = Response;
+
+        fn send(self) -> Response {
+            self.send_ref()
+        }
+
+        fn send_ref(&self) -> Response {
+            let flooded = self.attempts.fetch_add(1, Ordering::SeqCst) < self.failures;
+            Box::pin(async move {
+                if flooded {
+                    Err(RequestError::RetryAfter(RETRY_AFTER))
+                } else {
+                    Ok(True)
+                }
+            })
+        }
+    }
+
+    impl IntoFuture for FloodedRequest {
+        type Output = Result<True, RequestError>;
+        type IntoFuture = Response;
+
+        fn into_future(self) -> Response {
+            self.send()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_resends() {
+        let queue = SendQueue::new();
+        let request = FloodedRequest::new(1);
+        let attempts = Arc::clone(&request.attempts);
+
+        let started = Instant::now();
+        assert!(queue.send(None, Priority::Delivery, request).await.is_ok());
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() >= RETRY_AFTER);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_gives_up() {
+        let queue = SendQueue::new();
+        let request = FloodedRequest::new(usize::MAX);
+        let attempts = Arc::clone(&request.attempts);
+
+        let result = queue.send(None, Priority::Delivery, request).await;
+        assert!(matches!(result, Err(RequestError::RetryAfter(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), MAX_RETRIES as usize + 1);
+    }
+
+    #[test]
+    fn test_chat_interval() {
+        let mut limits = Limits::default();
+        let now = Instant::now();
+        let chat = Some(ChatId(42));
+
+        assert_eq!(limits.wait_time(chat, Priority::Delivery, now), None);
+        limits.record(chat, now);
+
+        // Same chat must wait, another chat may send right away
+        assert!(limits.wait_time(chat, Priority::Delivery, now).is_some());
+        assert_eq!(
+            limits.wait_time(Some(ChatId(43)), Priority::Delivery, now),
+            None
+        );
+        assert_eq!(
+            limits.wait_time(chat, Priority::Delivery, now + CHAT_INTERVAL),
+            None
+        );
+    }
+
+    #[test]
+    fn test_group_per_minute_limit() {
+        let mut limits = Limits::default();
+        let start = Instant::now();
+        let group = Some(ChatId(-100_123));
+
+        for i in 0..GROUP_PER_MINUTE as u64 {
+            limits.record(group, start + Duration::from_secs(i * 2));
+        }
+
+        let now = start + Duration::from_secs(GROUP_PER_MINUTE as u64 * 2);
+        let wait = limits.wait_time(group, Priority::Delivery, now).unwrap();
+        assert_eq!(wait, Duration::from_mins(1) - Duration::from_secs(40));
+    }
+
+    #[test]
+    fn test_progress_yields_to_delivery() {
+        let mut limits = Limits::default();
+        let now = Instant::now();
+        let chat = ChatId(42);
+        limits.chats.entry(chat).or_default().deliveries_waiting = 1;
+
+        assert_eq!(limits.wait_time(Some(chat), Priority::Delivery, now), None);
+        assert_eq!(
+            limits.wait_time(Some(chat), Priority::Progress, now),
+            Some(YIELD_INTERVAL)
+        );
+    }
+}
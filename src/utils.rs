@@ -1,43 +1,13 @@
-use regex::Regex;
+use crate::links::NeteaseLink;
 use std::path::Path;
 
-/// Global regex patterns for URL parsing
-static SONG_REGEX: std::sync::LazyLock<Regex> =
-    std::sync::LazyLock::new(|| Regex::new(r"music\.163\.com/.*?song.*?[?&]id=(\d+)").unwrap());
-
-static SHARE_LINK_REGEX: std::sync::LazyLock<Regex> = std::sync::LazyLock::new(|| {
-    Regex::new(r"(http|https)://[\w\-_]+(\.[\w\-_]+)+([\w\-.,@?^=%&:/~+#]*[\w\-@?^=%&/~+#])?")
-        .unwrap()
-});
-
-static NUMBER_REGEX: std::sync::LazyLock<Regex> =
-    std::sync::LazyLock::new(|| Regex::new(r"\d+").unwrap());
-
-/// Extract music ID from text
+/// Extract a song ID from a link, share text or bare number
+#[must_use]
 pub fn parse_music_id(text: &str) -> Option<u64> {
-    let text = text.replace(['\n', ' '], "");
-
-    // Try to extract from URL
-    if let Some(captures) = SONG_REGEX.captures(&text) {
-        if let Some(id_str) = captures.get(1) {
-            return id_str.as_str().parse().ok();
-        }
+    match NeteaseLink::parse(text)? {
+        NeteaseLink::Song(id) => Some(id),
+        _ => None,
     }
-
-    // Try to extract from share link
-    if let Some(url_match) = SHARE_LINK_REGEX.find(&text) {
-        if url_match.as_str().contains("song") {
-            if let Some(id_match) = NUMBER_REGEX.find(url_match.as_str()) {
-                return id_match.as_str().parse().ok();
-            }
-        }
-    }
-
-    // Try to parse as direct number (only if the entire text is a number)
-    if text.parse::<u64>().is_ok() {
-        return text.parse().ok();
-    }
-    None
 }
 
 /// Check if directory exists, create if not
@@ -109,6 +79,17 @@ pub fn format_duration(seconds: u64) -> String {
     format!("{minutes:02}:{seconds:02}")
 }
 
+/// Cut text to at most `max` characters, marking the cut with an ellipsis
+#[must_use]
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
 /// Check if an error is a timeout error
 pub fn is_timeout_error(error: &dyn std::error::Error) -> bool {
     error.to_string().contains("timeout") || error.to_string().contains("deadline")